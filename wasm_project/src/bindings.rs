@@ -0,0 +1,75 @@
+// wasm-bindgen wrappers (the `bindgen` feature) so JS can pass a Float32Array
+// straight in instead of managing buffers through alloc/dealloc. The raw
+// #[no_mangle] exports stay available alongside these.
+
+use js_sys::{Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::{code_name, metrics_or_invalid, AudioMetrics};
+
+// Classification code for the samples, as returned by process_audio_chunk.
+#[wasm_bindgen]
+pub fn analyze(samples: &[f32]) -> i32 {
+    crate::process_audio_chunk(samples.as_ptr(), samples.len())
+}
+
+// Metrics for the samples as a plain JS object with camelCase keys plus a
+// classificationName label; the same values that analyze_audio_chunk returns,
+// with nothing left in wasm memory for the caller to free.
+#[wasm_bindgen(js_name = analyzeMetrics)]
+pub fn analyze_metrics(samples: &[f32]) -> JsValue {
+    let metrics = metrics_or_invalid(samples.as_ptr(), samples.len());
+    let object = Object::new();
+    let name = code_name(metrics.classification).to_string_lossy();
+    let fields = metrics_fields(&metrics)
+        .into_iter()
+        .map(|(key, value)| (key, JsValue::from(value)))
+        .chain([("classificationName", JsValue::from_str(&name))]);
+    for (key, value) in fields {
+        // Only fails for frozen objects or proxies, never a fresh Object.
+        let _ = Reflect::set(&object, &JsValue::from_str(key), &value);
+    }
+    object.into()
+}
+
+// The numeric properties of the analyzeMetrics object; JS numbers are f64.
+fn metrics_fields(m: &AudioMetrics) -> [(&'static str, f64); 7] {
+    [
+        ("rms", m.rms as f64),
+        ("peak", m.peak as f64),
+        ("classification", m.classification as f64),
+        ("clippedSampleCount", m.clipped_sample_count as f64),
+        ("zcr", m.zcr as f64),
+        ("crestFactor", m.crest_factor as f64),
+        ("spectralFlatness", m.spectral_flatness as f64),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // JsValue only exists inside a JS host, so natively this checks the
+    // values that analyzeMetrics copies onto its object.
+    #[test]
+    fn wrappers_match_the_raw_exports() {
+        let tone: Vec<f32> = (0..1024)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let loud = [0.95f32; 512];
+        for samples in [&tone[..], &loud[..], &[0.0; 256][..], &[][..]] {
+            let raw = crate::process_audio_chunk(samples.as_ptr(), samples.len());
+            assert_eq!(analyze(samples), raw);
+
+            let metrics = metrics_or_invalid(samples.as_ptr(), samples.len());
+            let fields = metrics_fields(&metrics);
+            assert_eq!(fields[0], ("rms", metrics.rms as f64));
+            assert_eq!(fields[2], ("classification", raw as f64));
+            assert_eq!(
+                fields[3],
+                ("clippedSampleCount", metrics.clipped_sample_count as f64)
+            );
+        }
+        assert_eq!(analyze(&tone), 1);
+    }
+}
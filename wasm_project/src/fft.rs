@@ -0,0 +1,378 @@
+// Pure-Rust radix-2 FFT used by the spectral analysis entry points.
+
+use std::f32::consts::PI;
+
+// Power of two closest to len (ties round up). Buffers are zero-padded or
+// truncated to this size before transforming.
+pub(crate) fn nearest_power_of_two(len: usize) -> usize {
+    let upper = len.next_power_of_two();
+    let lower = upper / 2;
+    if lower > 0 && len - lower < upper - len {
+        lower
+    } else {
+        upper
+    }
+}
+
+// Calls f(i, j) for every index pair i < j swapped by the bit-reversal
+// permutation of n elements.
+fn for_each_bit_reversed_pair(n: usize, mut f: impl FnMut(usize, usize)) {
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            f(i, j);
+        }
+    }
+}
+
+// Cooley-Tukey butterfly passes over bit-reversed input. twiddle(k, size)
+// returns exp(-2*pi*i*k/size) as (re, im).
+fn butterflies(re: &mut [f32], im: &mut [f32], twiddle: impl Fn(usize, usize) -> (f32, f32)) {
+    let n = re.len();
+    let mut size = 2;
+    while size <= n {
+        let half = size / 2;
+        for start in (0..n).step_by(size) {
+            for k in 0..half {
+                let (w_re, w_im) = twiddle(k, size);
+                let a = start + k;
+                let b = a + half;
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+}
+
+// In-place iterative Cooley-Tukey FFT. Both slices must have the same
+// power-of-two length. Twiddles are computed on the fly; use FftPlan when the
+// same size is transformed repeatedly.
+pub(crate) fn fft_in_place(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+    for_each_bit_reversed_pair(n, |i, j| {
+        re.swap(i, j);
+        im.swap(i, j);
+    });
+    butterflies(re, im, |k, size| {
+        let (w_im, w_re) = (-2.0 * PI * k as f32 / size as f32).sin_cos();
+        (w_re, w_im)
+    });
+}
+
+// Twiddle factors and bit-reversal swaps for one power-of-two size, computed
+// once so repeated transforms skip the per-call sin/cos work.
+pub struct FftPlan {
+    size: usize,
+    // exp(-2*pi*i*k/size) as (re, im) for k in 0..size/2.
+    twiddles: Vec<(f32, f32)>,
+    swaps: Vec<(usize, usize)>,
+}
+
+impl FftPlan {
+    // None unless size is a power of two.
+    pub(crate) fn new(size: usize) -> Option<Self> {
+        if !size.is_power_of_two() {
+            return None;
+        }
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let (w_im, w_re) = (-2.0 * std::f64::consts::PI * k as f64 / size as f64).sin_cos();
+                (w_re as f32, w_im as f32)
+            })
+            .collect();
+        let mut swaps = Vec::new();
+        for_each_bit_reversed_pair(size, |i, j| swaps.push((i, j)));
+        Some(FftPlan {
+            size,
+            twiddles,
+            swaps,
+        })
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    // Same result as fft_in_place; both slices must be size() long.
+    pub(crate) fn forward(&self, re: &mut [f32], im: &mut [f32]) {
+        debug_assert!(re.len() == self.size && im.len() == self.size);
+        for &(i, j) in &self.swaps {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+        // A stage of width `size` uses every (self.size / size)-th twiddle.
+        butterflies(re, im, |k, size| self.twiddles[k * (self.size / size)]);
+    }
+}
+
+// Multiplies the samples by a periodic Hann window in place. The coefficients
+// are computed on every call (one cos per sample) rather than cached: that
+// keeps the helper stateless and works for any length, at the cost of
+// recomputing the same window when the chunk size never changes.
+pub(crate) fn hann_window(samples: &mut [f32]) {
+    let n = samples.len() as f32;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        *sample *= 0.5 - 0.5 * (2.0 * PI * i as f32 / n).cos();
+    }
+}
+
+// One-sided power spectrum |X[k]|^2 for bins 0..=n/2, where n is the plan's
+// size if one is given and otherwise the transform size chosen by
+// nearest_power_of_two. With `window` set, the copied samples are
+// Hann-windowed before zero padding. Returns (power, n).
+pub(crate) fn power_spectrum(
+    samples: &[f32],
+    window: bool,
+    plan: Option<&FftPlan>,
+) -> (Vec<f32>, usize) {
+    let n = plan.map_or_else(|| nearest_power_of_two(samples.len()), FftPlan::size);
+    let mut re = vec![0.0; n];
+    let copied = samples.len().min(n);
+    re[..copied].copy_from_slice(&samples[..copied]);
+    if window {
+        hann_window(&mut re[..copied]);
+    }
+    let mut im = vec![0.0; n];
+
+    match plan {
+        Some(plan) => plan.forward(&mut re, &mut im),
+        None => fft_in_place(&mut re, &mut im),
+    }
+
+    let power = re[..=n / 2]
+        .iter()
+        .zip(&im[..=n / 2])
+        .map(|(r, i)| r * r + i * i)
+        .collect();
+    (power, n)
+}
+
+// Segment length for averaged spectra. Averaging the periodograms of several
+// short segments gives a stable flatness estimate; a single raw periodogram of
+// white noise only reaches a flatness of about 0.56.
+pub(crate) const AVERAGING_SEGMENT: usize = 256;
+
+// Below two full segments there is too little to average, so the short path
+// uses segments of a quarter of the input instead, never fewer than this.
+const MIN_SHORT_SEGMENT: usize = 8;
+
+thread_local! {
+    // Shared by every averaged spectrum of a full-length segment, which is
+    // what chunk classification computes on every call.
+    static SEGMENT_PLAN: FftPlan =
+        FftPlan::new(AVERAGING_SEGMENT).expect("AVERAGING_SEGMENT is a power of two");
+}
+
+// Mean one-sided power spectrum over consecutive AVERAGING_SEGMENT-sample
+// segments of the `len` samples yielded by `samples` (a trailing partial
+// segment is dropped), or segments of the plan's size if one is given. Only
+// one segment is buffered at a time.
+//
+// Without a plan, inputs shorter than two segments are buffered whole and
+// averaged over Hann-windowed segments of a quarter of their length (rounded
+// down to a power of two) at a 50% hop, with the last segment aligned to the
+// end so the tail counts. Inputs too short for that fall back to one
+// zero-padded segment sized by nearest_power_of_two.
+pub(crate) fn averaged_power_spectrum(
+    samples: impl Iterator<Item = f32>,
+    len: usize,
+    plan: Option<&FftPlan>,
+) -> Vec<f32> {
+    match plan {
+        Some(plan) => averaged_with(samples, len, plan.size(), |re, im| plan.forward(re, im)),
+        None if len >= 2 * AVERAGING_SEGMENT => SEGMENT_PLAN.with(|plan| {
+            averaged_with(samples, len, AVERAGING_SEGMENT, |re, im| {
+                plan.forward(re, im)
+            })
+        }),
+        None if len >= 4 * MIN_SHORT_SEGMENT => {
+            overlapped_average(&samples.take(len).collect::<Vec<_>>())
+        }
+        None => averaged_with(samples, len, nearest_power_of_two(len), fft_in_place),
+    }
+}
+
+// The short-input path of averaged_power_spectrum; `samples` holds at least
+// 4 * MIN_SHORT_SEGMENT values.
+fn overlapped_average(samples: &[f32]) -> Vec<f32> {
+    // Largest power of two no more than a quarter of the input.
+    let n = (samples.len() / 4 + 1).next_power_of_two() / 2;
+    let hop = n / 2;
+    let last = samples.len() - n;
+    let mut starts: Vec<usize> = (0..=last).step_by(hop).collect();
+    if starts.last() != Some(&last) {
+        starts.push(last);
+    }
+
+    let mut total = vec![0.0; n / 2 + 1];
+    let mut re = vec![0.0; n];
+    let mut im = vec![0.0; n];
+    for &start in &starts {
+        re.copy_from_slice(&samples[start..start + n]);
+        hann_window(&mut re);
+        im.fill(0.0);
+        fft_in_place(&mut re, &mut im);
+        for (k, bin) in total.iter_mut().enumerate() {
+            *bin += re[k] * re[k] + im[k] * im[k];
+        }
+    }
+
+    for bin in total.iter_mut() {
+        *bin /= starts.len() as f32;
+    }
+    total
+}
+
+fn averaged_with(
+    mut samples: impl Iterator<Item = f32>,
+    len: usize,
+    n: usize,
+    transform: impl Fn(&mut [f32], &mut [f32]),
+) -> Vec<f32> {
+    let segments = (len / n).max(1);
+    let mut total = vec![0.0; n / 2 + 1];
+    let mut re = vec![0.0; n];
+    let mut im = vec![0.0; n];
+
+    for _ in 0..segments {
+        im.fill(0.0);
+        for slot in re.iter_mut() {
+            *slot = samples.next().unwrap_or(0.0);
+        }
+        transform(&mut re, &mut im);
+        for (k, bin) in total.iter_mut().enumerate() {
+            *bin += re[k] * re[k] + im[k] * im[k];
+        }
+    }
+
+    for bin in total.iter_mut() {
+        *bin /= segments as f32;
+    }
+    total
+}
+
+// Geometric mean over arithmetic mean of the non-DC power bins: near 1.0 for
+// white noise, near 0.0 for a pure tone, 0.0 when there is no energy.
+pub(crate) fn flatness(power: &[f32]) -> f32 {
+    let bins = power.get(1..).unwrap_or(&[]);
+    if bins.is_empty() {
+        return 0.0;
+    }
+    let count = bins.len() as f64;
+    let arithmetic = bins.iter().map(|&p| p as f64).sum::<f64>() / count;
+    if arithmetic <= f64::MIN_POSITIVE {
+        return 0.0;
+    }
+    // The tiny floor keeps a single empty bin from sending ln() to -inf.
+    let log_mean = bins.iter().map(|&p| (p as f64 + 1e-20).ln()).sum::<f64>() / count;
+    (log_mean.exp() / arithmetic) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_power_of_two_pads_or_truncates() {
+        assert_eq!(nearest_power_of_two(1), 1);
+        assert_eq!(nearest_power_of_two(1000), 1024);
+        assert_eq!(nearest_power_of_two(1024), 1024);
+        assert_eq!(nearest_power_of_two(1500), 1024);
+        assert_eq!(nearest_power_of_two(1600), 2048);
+    }
+
+    #[test]
+    fn hann_window_tapers_to_zero_at_the_start() {
+        let mut samples = [1.0f32; 8];
+        hann_window(&mut samples);
+        assert_eq!(samples[0], 0.0);
+        assert!((samples[4] - 1.0).abs() < 1e-6);
+        assert!((samples[2] - samples[6]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn planned_fft_matches_a_naive_dft() {
+        let input = [0.5f32, -1.0, 0.25, 2.0, 0.0, -0.75, 1.5, 0.125];
+        let n = input.len();
+        let plan = FftPlan::new(n).unwrap();
+        let mut re = input;
+        let mut im = [0.0f32; 8];
+        plan.forward(&mut re, &mut im);
+
+        for k in 0..n {
+            let (mut dft_re, mut dft_im) = (0.0f64, 0.0f64);
+            for (t, &x) in input.iter().enumerate() {
+                let angle = -2.0 * std::f64::consts::PI * (k * t) as f64 / n as f64;
+                dft_re += x as f64 * angle.cos();
+                dft_im += x as f64 * angle.sin();
+            }
+            assert!((re[k] as f64 - dft_re).abs() < 1e-5, "re[{k}]");
+            assert!((im[k] as f64 - dft_im).abs() < 1e-5, "im[{k}]");
+        }
+
+        let (mut plain_re, mut plain_im) = (input, [0.0f32; 8]);
+        fft_in_place(&mut plain_re, &mut plain_im);
+        for k in 0..n {
+            assert!((re[k] - plain_re[k]).abs() < 1e-5 && (im[k] - plain_im[k]).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn plans_require_a_power_of_two() {
+        assert!(FftPlan::new(0).is_none());
+        assert!(FftPlan::new(12).is_none());
+        assert_eq!(FftPlan::new(1024).map(|p| p.size()), Some(1024));
+    }
+
+    #[test]
+    fn averaged_spectrum_is_the_same_with_the_cached_plan() {
+        let samples: Vec<f32> = (0..1000)
+            .map(|i| ((i * 37) % 101) as f32 / 50.0 - 1.0)
+            .collect();
+        let cached = averaged_power_spectrum(samples.iter().copied(), samples.len(), None);
+        assert_eq!(cached.len(), AVERAGING_SEGMENT / 2 + 1);
+
+        let (mut re, mut im) = (vec![0.0; AVERAGING_SEGMENT], vec![0.0; AVERAGING_SEGMENT]);
+        let mut expected = vec![0.0f32; AVERAGING_SEGMENT / 2 + 1];
+        for segment in samples.chunks_exact(AVERAGING_SEGMENT) {
+            re.copy_from_slice(segment);
+            im.fill(0.0);
+            fft_in_place(&mut re, &mut im);
+            for (k, bin) in expected.iter_mut().enumerate() {
+                *bin += (re[k] * re[k] + im[k] * im[k]) / 3.0;
+            }
+        }
+        for (c, e) in cached.iter().zip(&expected) {
+            assert!((c - e).abs() <= 1e-3 * e.max(1.0), "{c} vs {e}");
+        }
+
+        // A plan sets the segment length instead.
+        let plan = FftPlan::new(64).unwrap();
+        let planned = averaged_power_spectrum(samples.iter().copied(), samples.len(), Some(&plan));
+        assert_eq!(planned.len(), 33);
+    }
+
+    #[test]
+    fn impulse_has_flat_spectrum() {
+        let mut re = [0.0f32; 8];
+        re[0] = 1.0;
+        let mut im = [0.0f32; 8];
+        fft_in_place(&mut re, &mut im);
+        for k in 0..8 {
+            assert!((re[k] - 1.0).abs() < 1e-6);
+            assert!(im[k].abs() < 1e-6);
+        }
+    }
+}
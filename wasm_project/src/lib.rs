@@ -1,54 +1,4708 @@
+// Exported functions take raw pointers from the JS host by design.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+#[cfg(feature = "bindgen")]
+mod bindings;
+mod fft;
+
+use fft::FftPlan;
+
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::Write;
+use std::panic::AssertUnwindSafe;
+
+// Metrics produced by `analyze_audio_chunk`.
+//
+// Layout (little-endian, 28 bytes total) for reading with a JS DataView:
+//   offset 0:  rms                  f32
+//   offset 4:  peak                 f32
+//   offset 8:  classification       i32 (same codes as process_audio_chunk, -1 for invalid input)
+//   offset 12: clipped_sample_count u32
+//   offset 16: zcr                  f32 (zero crossings per sample)
+//   offset 20: crest_factor         f32 (peak / rms, 0 for silence)
+//   offset 24: spectral_flatness    f32 (0.0 tonal .. 1.0 white noise)
+#[repr(C)]
+pub struct AudioMetrics {
+    pub rms: f32,
+    pub peak: f32,
+    pub classification: i32,
+    pub clipped_sample_count: u32,
+    pub zcr: f32,
+    pub crest_factor: f32,
+    pub spectral_flatness: f32,
+}
+
+// Signal quality codes returned across the FFI boundary.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Classification {
+    // Too quiet.
+    Silence = 0,
+    Good = 1,
+    // Too loud/distorted.
+    Clipping = 2,
+    // High spectral flatness - likely background noise/hiss.
+    Noisy = 3,
+}
+
+impl Classification {
+    fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Classification::Silence),
+            1 => Some(Classification::Good),
+            2 => Some(Classification::Clipping),
+            3 => Some(Classification::Noisy),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static CStr {
+        match self {
+            Classification::Silence => c"Silence",
+            Classification::Good => c"Good",
+            Classification::Clipping => c"Clipping",
+            Classification::Noisy => c"Noisy",
+        }
+    }
+
+    // Ranks classifications so the worst one wins: clipping, then silence.
+    fn severity(self) -> u8 {
+        match self {
+            Classification::Clipping => 3,
+            Classification::Silence => 2,
+            Classification::Noisy => 1,
+            Classification::Good => 0,
+        }
+    }
+}
+
+// Errors share the i32 return channel with Classification but are always
+// negative, so `code < 0` is enough for a caller to detect failure.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    // Empty or null buffer, or an out-of-range argument.
+    InvalidInput = -1,
+    // A guarded entry point panicked; see `guarded`.
+    Panicked = -99,
+}
+
+impl ErrorCode {
+    fn from_code(code: i32) -> Option<Self> {
+        match code {
+            -1 => Some(ErrorCode::InvalidInput),
+            -99 => Some(ErrorCode::Panicked),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static CStr {
+        match self {
+            ErrorCode::InvalidInput => c"Invalid input",
+            ErrorCode::Panicked => c"Internal error",
+        }
+    }
+}
+
+const INVALID_INPUT: i32 = ErrorCode::InvalidInput as i32;
+const PANICKED: i32 = ErrorCode::Panicked as i32;
+
+// Runs the body of an entry point, turning a panic into `fallback` so it never
+// unwinds across the FFI boundary and the host can keep the session going.
+// Exports return -99 (via guarded), NaN, null, 0 or nothing, matching their
+// type. This only catches anything when the module is built with
+// panic = "unwind"; under the usual wasm32 panic = "abort" profile a panic
+// still traps. Stateful objects (trackers, VADs, ...) may be left mid-update
+// by a caught panic.
+fn guarded_or<R>(fallback: R, body: impl FnOnce() -> R) -> R {
+    std::panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(fallback)
+}
+
+fn guarded(body: impl FnOnce() -> i32) -> i32 {
+    guarded_or(PANICKED, body)
+}
+
+#[derive(Clone, Copy)]
+struct Thresholds {
+    silence: f32,
+    clipping: f32,
+}
+
+// WASM runs the module on a single thread, so a thread-local Cell is all the
+// guarding the runtime settings need.
+thread_local! {
+    static THRESHOLDS: Cell<Thresholds> = const {
+        Cell::new(Thresholds { silence: 0.01, clipping: 0.9 })
+    };
+    static REMOVE_DC: Cell<bool> = const { Cell::new(false) };
+    static TRIM_MARGIN: Cell<usize> = const { Cell::new(0) };
+}
+
+fn thresholds() -> Thresholds {
+    THRESHOLDS.with(Cell::get)
+}
+
+// Spectral flatness above which an otherwise Good signal is treated as noise.
+const NOISY_FLATNESS: f32 = 0.5;
+
+// Samples at or above this magnitude count as clipped.
+const CLIP_SAMPLE_LEVEL: f32 = 0.99;
+
+// Borrows the caller's buffer, rejecting empty and null inputs.
+fn input_slice<'a, T>(ptr: *const T, len: usize) -> Option<&'a [T]> {
+    if ptr.is_null() || len == 0 {
+        return None;
+    }
+    Some(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+fn input_slice_mut<'a, T>(ptr: *mut T, len: usize) -> Option<&'a mut [T]> {
+    if ptr.is_null() || len == 0 {
+        return None;
+    }
+    Some(unsafe { std::slice::from_raw_parts_mut(ptr, len) })
+}
+
+fn classify(rms: f32, flatness: f32) -> Classification {
+    let limits = thresholds();
+    if rms < limits.silence {
+        Classification::Silence
+    } else if rms > limits.clipping {
+        Classification::Clipping
+    } else if flatness > NOISY_FLATNESS {
+        Classification::Noisy
+    } else {
+        Classification::Good
+    }
+}
+
+// Whether a metrics pass measures spectral flatness, whose FFTs dominate its
+// cost. Classification only needs it to tell Good from Noisy, so paths that
+// just want a category skip it for Silence and Clipping chunks (reporting
+// 0.0).
+#[derive(Clone, Copy, PartialEq)]
+enum Flatness {
+    Always,
+    WhenClassifying,
+}
+
+fn compute_metrics(slice: &[f32]) -> AudioMetrics {
+    compute_metrics_with(slice, Flatness::Always)
+}
+
+// Classification of the chunk, measuring flatness only when it decides the
+// result.
+fn classify_chunk(slice: &[f32]) -> i32 {
+    compute_metrics_with(slice, Flatness::WhenClassifying).classification
+}
+
+fn compute_metrics_with(slice: &[f32], flatness: Flatness) -> AudioMetrics {
+    let offset = if REMOVE_DC.with(Cell::get) {
+        mean(slice.iter().copied())
+    } else {
+        0.0
+    };
+    let stats = slice_stats(slice, offset);
+    metrics_from_stats(stats, slice.iter().map(move |s| s - offset), flatness)
+}
+
+// RMS after optional DC removal, for callers that only need the level.
+fn chunk_rms(slice: &[f32]) -> f32 {
+    let sum = if REMOVE_DC.with(Cell::get) {
+        slice_stats(slice, mean(slice.iter().copied())).sum_squares
+    } else {
+        sum_squares(slice)
+    };
+    (sum / slice.len() as f32).sqrt()
+}
+
+// Works from an iterator so converted inputs (e.g. i16 PCM) can be analyzed
+// without materializing a float buffer. The samples are walked a second time
+// for the spectral flatness, and once more up front to find the mean when DC
+// removal is enabled. Float slices go through compute_metrics_with instead,
+// which can take the time-domain stats in vector lanes.
+fn compute_metrics_from(
+    samples: impl Iterator<Item = f32> + Clone,
+    flatness: Flatness,
+) -> AudioMetrics {
+    let offset = if REMOVE_DC.with(Cell::get) {
+        mean(samples.clone())
+    } else {
+        0.0
+    };
+    let samples = samples.map(move |s| s - offset);
+    metrics_from_stats(scalar_stats(samples.clone()), samples, flatness)
+}
+
+fn metrics_from_stats(
+    stats: SampleStats,
+    samples: impl Iterator<Item = f32> + Clone,
+    flatness: Flatness,
+) -> AudioMetrics {
+    let len = stats.len;
+    let rms = (stats.sum_squares / len as f32).sqrt();
+    let zcr = stats.zero_crossings as f32 / len as f32;
+    let limits = thresholds();
+    // Exactly the range where classify() consults the flatness.
+    let spectral_flatness =
+        if flatness == Flatness::Always || (limits.silence..=limits.clipping).contains(&rms) {
+            fft::flatness(&fft::averaged_power_spectrum(samples, len, None))
+        } else {
+            0.0
+        };
+
+    AudioMetrics {
+        rms,
+        peak: stats.peak,
+        classification: classify(rms, spectral_flatness) as i32,
+        clipped_sample_count: stats.clipped,
+        zcr,
+        crest_factor: crest_factor(stats.peak, rms),
+        spectral_flatness,
+    }
+}
+
+// Time-domain totals behind AudioMetrics, gathered in a single pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct SampleStats {
+    len: usize,
+    sum_squares: f32,
+    peak: f32,
+    clipped: u32,
+    zero_crossings: u32,
+}
+
+fn crosses_zero(prev: f32, sample: f32) -> bool {
+    (sample >= 0.0 && prev < 0.0) || (sample < 0.0 && prev >= 0.0)
+}
+
+fn scalar_stats(samples: impl Iterator<Item = f32>) -> SampleStats {
+    let mut stats = SampleStats::default();
+    let mut prev = None;
+    for sample in samples {
+        stats.len += 1;
+        stats.sum_squares += sample * sample;
+
+        let magnitude = sample.abs();
+        stats.peak = stats.peak.max(magnitude);
+        if magnitude >= CLIP_SAMPLE_LEVEL {
+            stats.clipped += 1;
+        }
+
+        if let Some(prev) = prev {
+            if crosses_zero(prev, sample) {
+                stats.zero_crossings += 1;
+            }
+        }
+        prev = Some(sample);
+    }
+    stats
+}
+
+fn scalar_sum_squares(slice: &[f32]) -> f32 {
+    slice.iter().map(|s| s * s).sum()
+}
+
+// Sums four lanes at a time with a scalar loop for the remainder.
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+fn simd_sum_squares(slice: &[f32]) -> f32 {
+    use core::arch::wasm32::{f32x4_add, f32x4_mul, f32x4_splat, v128, v128_load};
+
+    let chunks = slice.chunks_exact(4);
+    let remainder = chunks.remainder();
+    let mut acc = f32x4_splat(0.0);
+    for chunk in chunks {
+        // v128_load has no alignment requirement.
+        let v = unsafe { v128_load(chunk.as_ptr() as *const v128) };
+        acc = f32x4_add(acc, f32x4_mul(v, v));
+    }
+    f32_lanes(acc).iter().sum::<f32>() + scalar_sum_squares(remainder)
+}
+
+// slice_stats with every total taken four lanes at a time. Zero crossings
+// compare each vector with the one starting a sample earlier, so the pairs
+// ending in the first vector and in the remainder are counted in scalar.
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+fn simd_stats(slice: &[f32], offset: f32) -> SampleStats {
+    use core::arch::wasm32::*;
+
+    let len = slice.len();
+    let full = len - len % 4;
+    let ptr = slice.as_ptr();
+    let zero = f32x4_splat(0.0);
+    let shift = f32x4_splat(offset);
+    let clip_level = f32x4_splat(CLIP_SAMPLE_LEVEL);
+    let load = |i: usize| f32x4_sub(unsafe { v128_load(ptr.add(i) as *const v128) }, shift);
+
+    let mut sum = f32x4_splat(0.0);
+    let mut peak = f32x4_splat(0.0);
+    // Comparison masks are -1 per true lane, so subtracting them counts.
+    let mut clipped = i32x4_splat(0);
+    let mut crossings = i32x4_splat(0);
+    for start in (0..full).step_by(4) {
+        let v = load(start);
+        sum = f32x4_add(sum, f32x4_mul(v, v));
+        let magnitude = f32x4_abs(v);
+        // pmax keeps the accumulator when the sample is NaN, like f32::max.
+        peak = f32x4_pmax(peak, magnitude);
+        clipped = i32x4_sub(clipped, f32x4_ge(magnitude, clip_level));
+        if start > 0 {
+            let prev = load(start - 1);
+            let rising = v128_and(f32x4_ge(v, zero), f32x4_lt(prev, zero));
+            let falling = v128_and(f32x4_lt(v, zero), f32x4_ge(prev, zero));
+            crossings = i32x4_sub(crossings, v128_or(rising, falling));
+        }
+    }
+
+    let mut stats = SampleStats {
+        len,
+        sum_squares: f32_lanes(sum).iter().sum(),
+        peak: f32_lanes(peak).into_iter().fold(0.0, f32::max),
+        clipped: i32_lanes(clipped).iter().sum::<i32>() as u32,
+        zero_crossings: i32_lanes(crossings).iter().sum::<i32>() as u32,
+    };
+    for &sample in &slice[full..] {
+        let sample = sample - offset;
+        stats.sum_squares += sample * sample;
+        stats.peak = stats.peak.max(sample.abs());
+        if sample.abs() >= CLIP_SAMPLE_LEVEL {
+            stats.clipped += 1;
+        }
+    }
+    for i in (1..len.min(4)).chain(full.max(4)..len) {
+        if crosses_zero(slice[i - 1] - offset, slice[i] - offset) {
+            stats.zero_crossings += 1;
+        }
+    }
+    stats
+}
+
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+fn f32_lanes(v: core::arch::wasm32::v128) -> [f32; 4] {
+    use core::arch::wasm32::f32x4_extract_lane;
+    [
+        f32x4_extract_lane::<0>(v),
+        f32x4_extract_lane::<1>(v),
+        f32x4_extract_lane::<2>(v),
+        f32x4_extract_lane::<3>(v),
+    ]
+}
+
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+fn i32_lanes(v: core::arch::wasm32::v128) -> [i32; 4] {
+    use core::arch::wasm32::i32x4_extract_lane;
+    [
+        i32x4_extract_lane::<0>(v),
+        i32x4_extract_lane::<1>(v),
+        i32x4_extract_lane::<2>(v),
+        i32x4_extract_lane::<3>(v),
+    ]
+}
+
+// The `simd` feature only takes effect on wasm32 builds with +simd128;
+// everything else uses the scalar loops.
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+fn sum_squares(slice: &[f32]) -> f32 {
+    simd_sum_squares(slice)
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128")))]
+fn sum_squares(slice: &[f32]) -> f32 {
+    scalar_sum_squares(slice)
+}
+
+// Stats of the samples after subtracting `offset` (the DC estimate, or 0.0).
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+fn slice_stats(slice: &[f32], offset: f32) -> SampleStats {
+    simd_stats(slice, offset)
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128")))]
+fn slice_stats(slice: &[f32], offset: f32) -> SampleStats {
+    scalar_stats(slice.iter().map(|s| s - offset))
+}
+
+fn mean(samples: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = samples.fold((0.0f64, 0usize), |(sum, count), s| {
+        (sum + s as f64, count + 1)
+    });
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f64) as f32
+    }
+}
+
+fn crest_factor(peak: f32, rms: f32) -> f32 {
+    if rms > f32::EPSILON {
+        peak / rms
+    } else {
+        0.0
+    }
+}
 
 #[no_mangle]
 pub extern "C" fn process_audio_chunk(ptr: *const f32, len: usize) -> i32 {
-    let slice = unsafe { std::slice::from_raw_parts(ptr, len) };
-    let mut sum_squares = 0.0;
-    let mut zero_crossings = 0;
+    guarded(|| match input_slice(ptr, len) {
+        Some(slice) => classify_chunk(slice),
+        None => INVALID_INPUT,
+    })
+}
 
-    for i in 0..len {
-        let sample = slice[i];
-        sum_squares += sample * sample;
+// Classifies samples [start, start + count) of a len-sample buffer without
+// copying them out. Returns -1 when the range is empty or runs past len.
+#[no_mangle]
+pub extern "C" fn process_audio_range(
+    ptr: *const f32,
+    len: usize,
+    start: usize,
+    count: usize,
+) -> i32 {
+    guarded(|| {
+        let range = input_slice(ptr, len).and_then(|buffer| {
+            let end = start.checked_add(count)?;
+            buffer.get(start..end).filter(|range| !range.is_empty())
+        });
+        match range {
+            Some(slice) => classify_chunk(slice),
+            None => INVALID_INPUT,
+        }
+    })
+}
+
+// Distance past a decision boundary, in dB of RMS, at which confidence reaches
+// 1.0 (sooner if the region is narrower, e.g. between the clipping threshold
+// and full scale).
+const CONFIDENCE_RAMP_DB: f32 = 6.0;
+// Same for spectral flatness either side of NOISY_FLATNESS.
+const CONFIDENCE_FLATNESS_RAMP: f32 = 0.25;
+
+// 0.0-1.0 confidence in metrics.classification: how far the RMS level sits
+// from the nearest threshold (in dB), and for Good/Noisy also how far the
+// flatness sits from NOISY_FLATNESS, taking the weaker of the two.
+fn classification_confidence(metrics: &AudioMetrics) -> f32 {
+    let limits = thresholds();
+    let level = to_dbfs(metrics.rms);
+    let (silence, clipping) = (to_dbfs(limits.silence), to_dbfs(limits.clipping));
+    let ramp = |distance: f32, room: f32| {
+        (distance / CONFIDENCE_RAMP_DB.min(room).max(f32::EPSILON)).clamp(0.0, 1.0)
+    };
+    match Classification::from_code(metrics.classification) {
+        Some(Classification::Silence) => ramp(silence - level, silence - DBFS_FLOOR),
+        // Full scale (0 dBFS) bounds the clipping region.
+        Some(Classification::Clipping) => ramp(level - clipping, -clipping),
+        Some(Classification::Good | Classification::Noisy) => {
+            let level_margin = (level - silence).min(clipping - level);
+            let loudness = ramp(level_margin, (clipping - silence) / 2.0);
+            let flatness_margin = (metrics.spectral_flatness - NOISY_FLATNESS).abs();
+            loudness.min((flatness_margin / CONFIDENCE_FLATNESS_RAMP).min(1.0))
+        }
+        None => 0.0,
+    }
+}
 
-        if i > 0 {
-            let prev = slice[i-1];
-            // Check if sign changed
-            if (sample >= 0.0 && prev < 0.0) || (sample < 0.0 && prev >= 0.0) {
-                zero_crossings += 1;
+// process_audio_chunk that also writes a 0.0-1.0 confidence to
+// out_confidence (if not null): near 0.0 when the chunk sits right on a
+// threshold, 1.0 when it is clearly inside its category. Invalid input
+// returns -1 with a confidence of 0.0.
+#[no_mangle]
+pub extern "C" fn classify_with_confidence(
+    ptr: *const f32,
+    len: usize,
+    out_confidence: *mut f32,
+) -> i32 {
+    guarded(|| {
+        let (classification, confidence) = match input_slice(ptr, len) {
+            Some(slice) => {
+                // Confidence only looks at flatness for Good and Noisy chunks.
+                let metrics = compute_metrics_with(slice, Flatness::WhenClassifying);
+                (metrics.classification, classification_confidence(&metrics))
             }
+            None => (INVALID_INPUT, 0.0),
+        };
+        if let Some(out) = unsafe { out_confidence.as_mut() } {
+            *out = confidence;
         }
+        classification
+    })
+}
+
+// Classifies total_len samples in chunk_len-sized windows, writing one code per
+// window to out_classifications (which must hold ceil(total_len / chunk_len)
+// entries). A trailing partial chunk is classified over its actual length, and
+// a chunk whose analysis panics gets -99 without affecting the others.
+// Returns the number of chunks written, 0 for invalid arguments.
+#[no_mangle]
+pub extern "C" fn process_chunks_batch(
+    ptr: *const f32,
+    total_len: usize,
+    chunk_len: usize,
+    out_classifications: *mut i32,
+) -> usize {
+    let slice = match input_slice(ptr, total_len) {
+        Some(slice) if chunk_len > 0 && !out_classifications.is_null() => slice,
+        _ => return 0,
+    };
+    let count = total_len.div_ceil(chunk_len);
+    let out = unsafe { std::slice::from_raw_parts_mut(out_classifications, count) };
+    for (code, chunk) in out.iter_mut().zip(slice.chunks(chunk_len)) {
+        *code = guarded(|| classify_chunk(chunk));
+    }
+    count
+}
+
+// Classifies 16-bit PCM, normalizing each sample by 32768 on the fly.
+#[no_mangle]
+pub extern "C" fn process_audio_chunk_i16(ptr: *const i16, len: usize) -> i32 {
+    guarded(|| match input_slice(ptr, len) {
+        Some(slice) => {
+            let samples = slice.iter().map(|&s| s as f32 / 32768.0);
+            compute_metrics_from(samples, Flatness::WhenClassifying).classification
+        }
+        None => INVALID_INPUT,
+    })
+}
+
+// Metrics for the buffer, or all zeros with classification -1 for invalid
+// input.
+fn metrics_or_invalid(ptr: *const f32, len: usize) -> AudioMetrics {
+    match input_slice(ptr, len) {
+        Some(slice) => compute_metrics(slice),
+        None => AudioMetrics {
+            rms: 0.0,
+            peak: 0.0,
+            classification: INVALID_INPUT,
+            clipped_sample_count: 0,
+            zcr: 0.0,
+            crest_factor: 0.0,
+            spectral_flatness: 0.0,
+        },
     }
+}
 
-    let rms = (sum_squares / len as f32).sqrt();
-    let zcr = zero_crossings as f32 / len as f32;
+// Returns a heap-allocated AudioMetrics; release it with free_audio_metrics.
+#[no_mangle]
+pub extern "C" fn analyze_audio_chunk(ptr: *const f32, len: usize) -> *mut AudioMetrics {
+    guarded_or(std::ptr::null_mut(), || {
+        Box::into_raw(Box::new(metrics_or_invalid(ptr, len)))
+    })
+}
 
-    // Thresholds
-    // 0: Silence (too quiet)
-    // 1: Good
-    // 2: Clipping (too loud/distorted)
-    // 3: Noisy (High ZCR - likely background noise/hiss)
+// Enough for every field at typical magnitudes, so building the string
+// normally takes a single allocation (tiny values print as long decimals).
+const METRICS_JSON_CAPACITY: usize = 256;
 
-    if rms < 0.01 {
-        return 0; // Silence
-    } else if rms > 0.9 {
-        return 2; // Clipping
-    } else if zcr > 0.35 {
-        return 3; // Noisy
+fn write_json_number(out: &mut String, value: f32) {
+    // JSON has no NaN or infinity.
+    if value.is_finite() {
+        let _ = write!(out, "{value}");
     } else {
-        return 1; // Good
+        out.push_str("null");
     }
 }
 
+fn metrics_json(metrics: &AudioMetrics) -> String {
+    let mut out = String::with_capacity(METRICS_JSON_CAPACITY);
+    out.push_str("{\"rms\":");
+    write_json_number(&mut out, metrics.rms);
+    out.push_str(",\"peak\":");
+    write_json_number(&mut out, metrics.peak);
+    // Labels are plain ASCII without quotes, so they need no escaping.
+    let label = code_name(metrics.classification)
+        .to_str()
+        .unwrap_or("Unknown");
+    let _ = write!(
+        out,
+        ",\"classification\":\"{label}\",\"classification_code\":{},\"clipped_sample_count\":{}",
+        metrics.classification, metrics.clipped_sample_count
+    );
+    out.push_str(",\"zcr\":");
+    write_json_number(&mut out, metrics.zcr);
+    out.push_str(",\"crest_factor\":");
+    write_json_number(&mut out, metrics.crest_factor);
+    out.push_str(",\"spectral_flatness\":");
+    write_json_number(&mut out, metrics.spectral_flatness);
+    out.push('}');
+    out
+}
+
+// The same metrics as analyze_audio_chunk, as a null-terminated UTF-8 JSON
+// object, e.g. {"rms":0.12,"peak":0.4,"classification":"Good",...}. The
+// classification is given both as its label and as the numeric code; invalid
+// input yields "Invalid input" / -1 with zeroed numbers. Release the string
+// with free_json_string.
 #[no_mangle]
-pub extern "C" fn alloc(size: usize) -> *mut f32 {
-    let mut vec = Vec::with_capacity(size);
-    let ptr = vec.as_mut_ptr();
-    std::mem::forget(vec);
-    ptr
+pub extern "C" fn analyze_audio_chunk_json(ptr: *const f32, len: usize) -> *mut u8 {
+    guarded_or(std::ptr::null_mut(), || {
+        let json = metrics_json(&metrics_or_invalid(ptr, len));
+        match CString::new(json) {
+            Ok(json) => json.into_raw() as *mut u8,
+            Err(_) => std::ptr::null_mut(),
+        }
+    })
+}
+
+// Number of values extract_features writes.
+const FEATURE_COUNT: usize = 7;
+
+// Writes FEATURE_COUNT features to out_ptr in this fixed order:
+//   0 rms, 1 peak, 2 zcr, 3 spectral_centroid (Hz), 4 spectral_flatness,
+//   5 crest_factor, 6 dc_offset.
+// Slots 0-2, 4 and 5 are the analyze_audio_chunk values (after DC removal if
+// set_remove_dc is on); slot 3 is spectral_centroid and slot 6 dc_offset of
+// the raw samples. New features will only ever be appended. Returns the
+// number written, or 0 if out_len < FEATURE_COUNT, the input is empty or the
+// sample rate is not positive.
+#[no_mangle]
+pub extern "C" fn extract_features(
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+    out_ptr: *mut f32,
+    out_len: usize,
+) -> usize {
+    guarded_or(0, || {
+        if out_len < FEATURE_COUNT || sample_rate.is_nan() || sample_rate <= 0.0 {
+            return 0;
+        }
+        let (slice, out) = match (
+            input_slice(ptr, len),
+            input_slice_mut(out_ptr, FEATURE_COUNT),
+        ) {
+            (Some(slice), Some(out)) => (slice, out),
+            _ => return 0,
+        };
+        let metrics = compute_metrics(slice);
+        out.copy_from_slice(&[
+            metrics.rms,
+            metrics.peak,
+            metrics.zcr,
+            spectral_centroid(ptr, len, sample_rate),
+            metrics.spectral_flatness,
+            metrics.crest_factor,
+            mean(slice.iter().copied()),
+        ]);
+        FEATURE_COUNT
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_json_string(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = CString::from_raw(ptr as *mut c_char);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_audio_metrics(ptr: *mut AudioMetrics) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(ptr);
+    }
+}
+
+// Maximum absolute sample value, 0.0 for empty input.
+#[no_mangle]
+pub extern "C" fn peak_amplitude(ptr: *const f32, len: usize) -> f32 {
+    guarded_or(f32::NAN, || match input_slice(ptr, len) {
+        Some(slice) => slice.iter().fold(0.0, |peak: f32, s| peak.max(s.abs())),
+        None => 0.0,
+    })
+}
+
+// Number of samples with abs(sample) >= threshold, counting both positive and
+// negative saturation. A threshold that is not positive (or NaN) selects the
+// default 0.99 used for AudioMetrics::clipped_sample_count.
+#[no_mangle]
+pub extern "C" fn count_clipped_samples(ptr: *const f32, len: usize, threshold: f32) -> usize {
+    guarded_or(0, || {
+        let threshold = if threshold > 0.0 {
+            threshold
+        } else {
+            CLIP_SAMPLE_LEVEL
+        };
+        match input_slice(ptr, len) {
+            Some(slice) => slice.iter().filter(|s| s.abs() >= threshold).count(),
+            None => 0,
+        }
+    })
+}
+
+// Spectral flatness of the buffer: close to 1.0 for white noise and near 0.0
+// for a pure tone. Returns 0.0 for empty or all-zero input.
+#[no_mangle]
+pub extern "C" fn spectral_flatness(ptr: *const f32, len: usize) -> f32 {
+    spectral_flatness_with_plan(std::ptr::null(), ptr, len)
+}
+
+// spectral_flatness averaging segments of the plan's size rather than 256
+// samples. A null plan behaves like spectral_flatness.
+#[no_mangle]
+pub extern "C" fn spectral_flatness_with_plan(
+    plan: *const FftPlan,
+    ptr: *const f32,
+    len: usize,
+) -> f32 {
+    guarded_or(f32::NAN, || {
+        let plan = unsafe { plan.as_ref() };
+        match input_slice(ptr, len) {
+            Some(slice) => fft::flatness(&fft::averaged_power_spectrum(
+                slice.iter().copied(),
+                len,
+                plan,
+            )),
+            None => 0.0,
+        }
+    })
+}
+
+// Buckets abs(sample) into `bins` equal-width buckets spanning 0.0..=1.0 and
+// writes the counts to out_counts (which must hold `bins` entries). Samples at
+// or above 1.0 land in the last bucket. Counts are zeroed first, so an empty
+// buffer yields all zeros.
+#[no_mangle]
+pub extern "C" fn amplitude_histogram(
+    ptr: *const f32,
+    len: usize,
+    bins: usize,
+    out_counts: *mut u32,
+) {
+    guarded_or((), || {
+        let counts = match input_slice_mut(out_counts, bins) {
+            Some(counts) => counts,
+            None => return,
+        };
+        counts.fill(0);
+        for sample in input_slice(ptr, len).unwrap_or(&[]) {
+            let bucket = ((sample.abs() * bins as f32) as usize).min(bins - 1);
+            counts[bucket] += 1;
+        }
+    })
+}
+
+// Reported by rms_dbfs for silent or empty buffers instead of -inf.
+const DBFS_FLOOR: f32 = -120.0;
+
+fn to_dbfs(rms: f32) -> f32 {
+    (20.0 * rms.log10()).max(DBFS_FLOOR)
+}
+
+// RMS level in dBFS, floored at -120 dB.
+#[no_mangle]
+pub extern "C" fn rms_dbfs(ptr: *const f32, len: usize) -> f32 {
+    guarded_or(f32::NAN, || match input_slice(ptr, len) {
+        Some(slice) => to_dbfs(chunk_rms(slice)),
+        None => DBFS_FLOOR,
+    })
+}
+
+// Per-chunk totals kept by RmsTracker.
+#[derive(Clone, Copy, Default)]
+struct ChunkEnergy {
+    sum_squares: f64,
+    weighted_flatness: f64,
+    samples: f64,
+}
+
+// Moving-window classifier over the last `window_chunks` chunks. Running
+// totals are updated as chunks enter and leave the ring, so each push is O(1)
+// regardless of window size.
+pub struct RmsTracker {
+    ring: Vec<ChunkEnergy>,
+    next: usize,
+    total: ChunkEnergy,
+}
+
+impl RmsTracker {
+    fn push(&mut self, slice: &[f32]) -> i32 {
+        let metrics = compute_metrics(slice);
+        let samples = slice.len() as f64;
+        let chunk = ChunkEnergy {
+            sum_squares: (metrics.rms as f64).powi(2) * samples,
+            weighted_flatness: metrics.spectral_flatness as f64 * samples,
+            samples,
+        };
+
+        let evicted = std::mem::replace(&mut self.ring[self.next], chunk);
+        self.next = (self.next + 1) % self.ring.len();
+        // Clamp to zero so float drift can't leave a tiny negative energy.
+        self.total.sum_squares =
+            (self.total.sum_squares - evicted.sum_squares + chunk.sum_squares).max(0.0);
+        self.total.weighted_flatness = (self.total.weighted_flatness - evicted.weighted_flatness
+            + chunk.weighted_flatness)
+            .max(0.0);
+        self.total.samples += chunk.samples - evicted.samples;
+
+        let rms = (self.total.sum_squares / self.total.samples).sqrt() as f32;
+        let flatness = (self.total.weighted_flatness / self.total.samples) as f32;
+        classify(rms, flatness) as i32
+    }
+}
+
+// Returns null if window_chunks is 0. Release with destroy_rms_tracker.
+#[no_mangle]
+pub extern "C" fn create_rms_tracker(window_chunks: usize) -> *mut RmsTracker {
+    guarded_or(std::ptr::null_mut(), || {
+        if window_chunks == 0 {
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(RmsTracker {
+            ring: vec![ChunkEnergy::default(); window_chunks],
+            next: 0,
+            total: ChunkEnergy::default(),
+        }))
+    })
+}
+
+// Feeds one chunk and returns the classification of the moving window.
+#[no_mangle]
+pub extern "C" fn tracker_push(tracker: *mut RmsTracker, ptr: *const f32, len: usize) -> i32 {
+    guarded(
+        || match (unsafe { tracker.as_mut() }, input_slice(ptr, len)) {
+            (Some(tracker), Some(slice)) => tracker.push(slice),
+            _ => INVALID_INPUT,
+        },
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_rms_tracker(tracker: *mut RmsTracker) {
+    if tracker.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(tracker);
+    }
+}
+
+// Holds the reported classification until stable_chunks consecutive chunks
+// agree on a different one, so borderline signals don't flicker.
+pub struct Debouncer {
+    stable_chunks: usize,
+    reported: Option<i32>,
+    candidate: i32,
+    run: usize,
+}
+
+impl Debouncer {
+    fn push(&mut self, classification: i32) -> i32 {
+        let reported = match self.reported {
+            // The first chunk is reported as-is.
+            None => classification,
+            Some(reported) if classification == reported => {
+                self.run = 0;
+                reported
+            }
+            Some(reported) => {
+                if classification == self.candidate {
+                    self.run += 1;
+                } else {
+                    self.candidate = classification;
+                    self.run = 1;
+                }
+                if self.run >= self.stable_chunks {
+                    self.run = 0;
+                    classification
+                } else {
+                    reported
+                }
+            }
+        };
+        self.reported = Some(reported);
+        reported
+    }
+}
+
+// Returns null if stable_chunks is 0. Release with destroy_classifier_debouncer.
+#[no_mangle]
+pub extern "C" fn create_classifier_debouncer(stable_chunks: usize) -> *mut Debouncer {
+    guarded_or(std::ptr::null_mut(), || {
+        if stable_chunks == 0 {
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(Debouncer {
+            stable_chunks,
+            reported: None,
+            candidate: INVALID_INPUT,
+            run: 0,
+        }))
+    })
+}
+
+// Classifies the chunk and returns the debounced classification. Invalid
+// input returns -1 and leaves the debouncer's state untouched.
+#[no_mangle]
+pub extern "C" fn debouncer_push(deb: *mut Debouncer, ptr: *const f32, len: usize) -> i32 {
+    guarded(|| match (unsafe { deb.as_mut() }, input_slice(ptr, len)) {
+        (Some(deb), Some(slice)) => deb.push(classify_chunk(slice)),
+        _ => INVALID_INPUT,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_classifier_debouncer(deb: *mut Debouncer) {
+    if deb.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(deb);
+    }
+}
+
+// Energy-based voice activity detector with hysteresis: the gate opens when a
+// chunk's RMS reaches open_thresh and only closes after hangover_chunks
+// consecutive chunks fall below close_thresh.
+pub struct Vad {
+    open_thresh: f32,
+    close_thresh: f32,
+    hangover_chunks: usize,
+    open: bool,
+    quiet_run: usize,
+}
+
+impl Vad {
+    fn process(&mut self, rms: f32) -> i32 {
+        if !self.open {
+            if rms >= self.open_thresh {
+                self.open = true;
+                self.quiet_run = 0;
+            }
+        } else if rms < self.close_thresh {
+            self.quiet_run += 1;
+            if self.quiet_run >= self.hangover_chunks.max(1) {
+                self.open = false;
+            }
+        } else {
+            self.quiet_run = 0;
+        }
+        self.open as i32
+    }
+}
+
+// Returns null unless 0.0 <= close_thresh <= open_thresh. A hangover of 0
+// closes on the first quiet chunk. Release with destroy_vad.
+#[no_mangle]
+pub extern "C" fn create_vad(
+    open_thresh: f32,
+    close_thresh: f32,
+    hangover_chunks: usize,
+) -> *mut Vad {
+    guarded_or(std::ptr::null_mut(), || {
+        if !(0.0..=open_thresh).contains(&close_thresh) {
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(Vad {
+            open_thresh,
+            close_thresh,
+            hangover_chunks,
+            open: false,
+            quiet_run: 0,
+        }))
+    })
+}
+
+// Returns 1 while speech is detected, 0 for silence, -1 for invalid input.
+#[no_mangle]
+pub extern "C" fn vad_process(vad: *mut Vad, ptr: *const f32, len: usize) -> i32 {
+    guarded(|| match (unsafe { vad.as_mut() }, input_slice(ptr, len)) {
+        (Some(vad), Some(slice)) => vad.process(chunk_rms(slice)),
+        _ => INVALID_INPUT,
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn dealloc(ptr: *mut f32, size: usize) {
+pub extern "C" fn destroy_vad(vad: *mut Vad) {
+    if vad.is_null() {
+        return;
+    }
     unsafe {
-        let _ = Vec::from_raw_parts(ptr, 0, size);
+        let _ = Box::from_raw(vad);
+    }
+}
+
+// Measures how long the input has been continuously silent (classified as
+// Silence under the current thresholds), for auto-stopping a recording.
+// Durations are counted in samples so long runs don't accumulate rounding.
+pub struct SilenceTimer {
+    target_samples: u64,
+    silent_samples: u64,
+}
+
+impl SilenceTimer {
+    fn push(&mut self, samples: usize, classification: i32) -> i32 {
+        if classification != Classification::Silence as i32 {
+            self.silent_samples = 0;
+            return 0;
+        }
+        self.silent_samples = self.silent_samples.saturating_add(samples as u64);
+        (self.silent_samples >= self.target_samples) as i32
+    }
+}
+
+// Returns null unless both sample_rate and silence_ms are positive and finite.
+// Release with destroy_silence_timer.
+#[no_mangle]
+pub extern "C" fn create_silence_timer(sample_rate: f32, silence_ms: f32) -> *mut SilenceTimer {
+    guarded_or(std::ptr::null_mut(), || {
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+        if !(positive(sample_rate) && positive(silence_ms)) {
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(SilenceTimer {
+            target_samples: (silence_ms as f64 * sample_rate as f64 / 1000.0).ceil() as u64,
+            silent_samples: 0,
+        }))
+    })
+}
+
+// Adds the chunk's duration to the silence run, or resets it if the chunk is
+// not silent. Returns 1 once the run has lasted silence_ms, 0 before that, -1
+// for invalid input.
+#[no_mangle]
+pub extern "C" fn silence_timer_push(timer: *mut SilenceTimer, ptr: *const f32, len: usize) -> i32 {
+    guarded(
+        || match (unsafe { timer.as_mut() }, input_slice(ptr, len)) {
+            (Some(timer), Some(slice)) => timer.push(len, classify_chunk(slice)),
+            _ => INVALID_INPUT,
+        },
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_silence_timer(timer: *mut SilenceTimer) {
+    if timer.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(timer);
+    }
+}
+
+// Exponential moving average for jittery control values such as a per-chunk
+// RMS meter: output += alpha * (input - output), starting from 0.0.
+pub struct Smoother {
+    alpha: f32,
+    value: f32,
+}
+
+// alpha is clamped to 0.0..=1.0 (NaN counts as 0.0). Higher values respond
+// faster: 1.0 passes the input straight through, while 0.0 holds the output
+// at 0.0. Each push closes the fraction alpha of the remaining gap, so a step
+// is 1 - (1 - alpha)^n of the way there after n pushes. Release with
+// destroy_smoother.
+#[no_mangle]
+pub extern "C" fn create_smoother(alpha: f32) -> *mut Smoother {
+    Box::into_raw(Box::new(Smoother {
+        alpha: unit_coefficient(alpha),
+        value: 0.0,
+    }))
+}
+
+// Returns the smoothed value after taking in `value`, or 0.0 for a null
+// smoother.
+#[no_mangle]
+pub extern "C" fn smoother_push(sm: *mut Smoother, value: f32) -> f32 {
+    guarded_or(f32::NAN, || match unsafe { sm.as_mut() } {
+        Some(sm) => {
+            sm.value += sm.alpha * (value - sm.value);
+            sm.value
+        }
+        None => 0.0,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_smoother(sm: *mut Smoother) {
+    if sm.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(sm);
+    }
+}
+
+// Chunk power (mean square) corresponding to the -120 dBFS floor.
+const POWER_FLOOR: f32 = 1e-12;
+// Noise-floor smoothing: a quieter chunk pulls the floor down quickly, while
+// chunks within SNR_NOISE_BAND of it nudge it up slowly. A louder chunk only
+// nudges the floor towards the quietest of the last SNR_WINDOW_CHUNKS, so
+// speech shorter than the window can't drag it up but a lasting rise in the
+// background (or real noise after a run of digital silence) is followed.
+const SNR_FALL_RATE: f32 = 0.5;
+const SNR_RISE_RATE: f32 = 0.02;
+const SNR_NOISE_BAND: f32 = 4.0; // power ratio, ~6 dB
+                                 // About 6.4 s of 512-sample chunks at 16 kHz, 0.5 s of 128-sample ones at
+                                 // 48 kHz.
+const SNR_WINDOW_CHUNKS: usize = 200;
+
+// Tracks a noise floor across chunks to report each chunk's SNR.
+pub struct SnrEstimator {
+    noise_floor: Option<f32>,
+    // Powers of the most recent chunks, at most SNR_WINDOW_CHUNKS.
+    recent: VecDeque<f32>,
+}
+
+impl SnrEstimator {
+    fn push(&mut self, power: f32) -> f32 {
+        let power = power.max(POWER_FLOOR);
+        if self.recent.len() == SNR_WINDOW_CHUNKS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(power);
+
+        let floor = match self.noise_floor {
+            None => power,
+            Some(floor) if power < floor => floor + SNR_FALL_RATE * (power - floor),
+            Some(floor) if power < floor * SNR_NOISE_BAND => {
+                floor + SNR_RISE_RATE * (power - floor)
+            }
+            Some(floor) => {
+                let quietest = self.recent.iter().copied().fold(f32::INFINITY, f32::min);
+                floor + SNR_RISE_RATE * (quietest - floor).max(0.0)
+            }
+        }
+        .max(POWER_FLOOR);
+        self.noise_floor = Some(floor);
+        10.0 * (power / floor).log10()
+    }
+}
+
+// Release with destroy_snr_estimator.
+#[no_mangle]
+pub extern "C" fn create_snr_estimator() -> *mut SnrEstimator {
+    Box::into_raw(Box::new(SnrEstimator {
+        noise_floor: None,
+        recent: VecDeque::with_capacity(SNR_WINDOW_CHUNKS),
+    }))
+}
+
+// Updates the noise floor with this chunk and returns the chunk's SNR in dB
+// relative to it. The first chunk seeds the floor (0 dB). Returns 0.0 for
+// invalid input.
+#[no_mangle]
+pub extern "C" fn snr_push(est: *mut SnrEstimator, ptr: *const f32, len: usize) -> f32 {
+    guarded_or(f32::NAN, || {
+        match (unsafe { est.as_mut() }, input_slice(ptr, len)) {
+            (Some(est), Some(slice)) => est.push(chunk_rms(slice).powi(2)),
+            _ => 0.0,
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_snr_estimator(est: *mut SnrEstimator) {
+    if est.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(est);
+    }
+}
+
+// ITU-R BS.1770 gating: 400 ms blocks advancing in 100 ms steps (75% overlap),
+// an absolute gate at -70 LUFS and a relative gate 10 LU below the mean of the
+// blocks that pass it.
+const LOUDNESS_STEP_SECONDS: f32 = 0.1;
+const LOUDNESS_STEPS_PER_BLOCK: usize = 4;
+const LOUDNESS_ABSOLUTE_GATE: f64 = -70.0;
+const LOUDNESS_RELATIVE_GATE: f64 = -10.0;
+// The K-weighting shelf sits at ~1.7 kHz, so lower rates can't represent it.
+const LOUDNESS_MIN_SAMPLE_RATE: f32 = 8000.0;
+
+// Direct form I biquad with normalized coefficients (a0 = 1).
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Biquad {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+// The two K-weighting stages (high-frequency shelf, then RLB high-pass),
+// derived for any sample rate from the BS.1770 analog prototypes; at 48 kHz
+// they reproduce the coefficients tabulated in the spec.
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let (gain_db, f0, q) = (3.999843853973347, 1681.974450955533, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+fn block_loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+// Integrated (gated) loudness of a mono stream across pushes. Every completed
+// 400 ms block's mean square is kept so the relative gate can be re-evaluated
+// over the whole session: about 80 bytes per second of audio.
+pub struct LoudnessMeter {
+    filters: [Biquad; 2],
+    step_len: usize,
+    step_energy: f64,
+    step_filled: usize,
+    // Energy of the most recent steps, oldest first.
+    recent_steps: VecDeque<f64>,
+    blocks: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let [shelf, high_pass] = &mut self.filters;
+            let weighted = high_pass.process(shelf.process(sample as f64));
+            self.step_energy += weighted * weighted;
+            self.step_filled += 1;
+            if self.step_filled < self.step_len {
+                continue;
+            }
+
+            if self.recent_steps.len() == LOUDNESS_STEPS_PER_BLOCK {
+                self.recent_steps.pop_front();
+            }
+            self.recent_steps.push_back(self.step_energy);
+            self.step_energy = 0.0;
+            self.step_filled = 0;
+            if self.recent_steps.len() == LOUDNESS_STEPS_PER_BLOCK {
+                let block_len = (self.step_len * LOUDNESS_STEPS_PER_BLOCK) as f64;
+                self.blocks
+                    .push(self.recent_steps.iter().sum::<f64>() / block_len);
+            }
+        }
+    }
+
+    fn integrated(&self) -> f32 {
+        let gated_mean = |gate: f64| {
+            let (sum, count) = self
+                .blocks
+                .iter()
+                .filter(|&&z| block_loudness(z) > gate)
+                .fold((0.0, 0usize), |(sum, count), &z| (sum + z, count + 1));
+            (count > 0).then(|| sum / count as f64)
+        };
+        let loudness = gated_mean(LOUDNESS_ABSOLUTE_GATE)
+            .map(|ungated| block_loudness(ungated) + LOUDNESS_RELATIVE_GATE)
+            .and_then(|relative| gated_mean(relative.max(LOUDNESS_ABSOLUTE_GATE)))
+            .map(block_loudness);
+        match loudness {
+            Some(lufs) => lufs as f32,
+            None => DBFS_FLOOR,
+        }
+    }
+}
+
+// Returns null for sample rates below LOUDNESS_MIN_SAMPLE_RATE (or NaN).
+// Release with destroy_loudness_meter.
+#[no_mangle]
+pub extern "C" fn create_loudness_meter(sample_rate: f32) -> *mut LoudnessMeter {
+    guarded_or(std::ptr::null_mut(), || {
+        if !sample_rate.is_finite() || sample_rate < LOUDNESS_MIN_SAMPLE_RATE {
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(LoudnessMeter {
+            filters: k_weighting(sample_rate as f64),
+            step_len: (sample_rate * LOUDNESS_STEP_SECONDS).round() as usize,
+            step_energy: 0.0,
+            step_filled: 0,
+            recent_steps: VecDeque::with_capacity(LOUDNESS_STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+        }))
+    })
+}
+
+// K-weights the chunk and adds any blocks it completes. Chunks may be any
+// length; partial steps carry over to the next push. Returns 0, or -1 for
+// invalid input.
+#[no_mangle]
+pub extern "C" fn loudness_push(meter: *mut LoudnessMeter, ptr: *const f32, len: usize) -> i32 {
+    guarded(
+        || match (unsafe { meter.as_mut() }, input_slice(ptr, len)) {
+            (Some(meter), Some(slice)) => {
+                meter.push(slice);
+                0
+            }
+            _ => INVALID_INPUT,
+        },
+    )
+}
+
+// Integrated loudness in LUFS over everything pushed so far. Returns -120.0
+// until at least one full block above the absolute gate has been seen, or
+// for a null meter.
+#[no_mangle]
+pub extern "C" fn loudness_integrated(meter: *const LoudnessMeter) -> f32 {
+    guarded_or(f32::NAN, || match unsafe { meter.as_ref() } {
+        Some(meter) => meter.integrated(),
+        None => DBFS_FLOOR,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_loudness_meter(meter: *mut LoudnessMeter) {
+    if meter.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(meter);
+    }
+}
+
+const MAX_CHANNELS: usize = 8;
+
+// Metrics produced by `analyze_audio_chunk_interleaved`.
+//
+// Layout (little-endian, 40 bytes total):
+//   offset 0: channels       u32 (0 for invalid input)
+//   offset 4: classification i32 (worst channel, -1 for invalid input)
+//   offset 8: channel_rms    [f32; 8] (entries past `channels` are 0.0)
+#[repr(C)]
+pub struct InterleavedMetrics {
+    pub channels: u32,
+    pub classification: i32,
+    pub channel_rms: [f32; MAX_CHANNELS],
+}
+
+fn compute_interleaved_metrics(ptr: *const f32, len: usize, channels: usize) -> InterleavedMetrics {
+    // channels stays 0 unless the input is accepted.
+    let mut metrics = InterleavedMetrics {
+        channels: 0,
+        classification: INVALID_INPUT,
+        channel_rms: [0.0; MAX_CHANNELS],
+    };
+    if channels == 0 || channels > MAX_CHANNELS {
+        return metrics;
+    }
+    // A trailing partial frame is dropped rather than counted.
+    let frames = len / channels;
+    let slice = match input_slice(ptr, frames * channels) {
+        Some(slice) => slice,
+        None => return metrics,
+    };
+    metrics.channels = channels as u32;
+
+    let mut worst: Option<Classification> = None;
+    for ch in 0..channels {
+        let samples = slice.iter().skip(ch).step_by(channels).copied();
+        let channel = compute_metrics_from(samples, Flatness::WhenClassifying);
+        metrics.channel_rms[ch] = channel.rms;
+        let class = classify(channel.rms, channel.spectral_flatness);
+        if worst.is_none_or(|w| class.severity() > w.severity()) {
+            worst = Some(class);
+        }
+    }
+    metrics.classification = worst.map_or(INVALID_INPUT, |w| w as i32);
+    metrics
+}
+
+// Classifies interleaved multichannel audio by its worst channel.
+// Returns -1 if channels is 0 or above 8, or there is no complete frame.
+#[no_mangle]
+pub extern "C" fn process_audio_chunk_interleaved(
+    ptr: *const f32,
+    len: usize,
+    channels: usize,
+) -> i32 {
+    guarded(|| compute_interleaved_metrics(ptr, len, channels).classification)
+}
+
+// Returns a heap-allocated InterleavedMetrics; release it with free_interleaved_metrics.
+#[no_mangle]
+pub extern "C" fn analyze_audio_chunk_interleaved(
+    ptr: *const f32,
+    len: usize,
+    channels: usize,
+) -> *mut InterleavedMetrics {
+    guarded_or(std::ptr::null_mut(), || {
+        Box::into_raw(Box::new(compute_interleaved_metrics(ptr, len, channels)))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_interleaved_metrics(ptr: *mut InterleavedMetrics) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(ptr);
+    }
+}
+
+// Human-readable label for a classification or error code, as a pointer to a
+// static null-terminated UTF-8 string ("Unknown" for unrecognised codes).
+// The string lives for the life of the module and must not be freed.
+#[no_mangle]
+pub extern "C" fn classification_name(code: i32) -> *const u8 {
+    code_name(code).as_ptr() as *const u8
+}
+
+fn code_name(code: i32) -> &'static CStr {
+    Classification::from_code(code)
+        .map(Classification::name)
+        .or_else(|| ErrorCode::from_code(code).map(ErrorCode::name))
+        .unwrap_or(c"Unknown")
+}
+
+// Averages interleaved channels into mono. out_ptr must have room for at
+// least len / channels samples; a trailing partial frame is ignored. out_ptr
+// may equal ptr to downmix in place. Returns the number of mono samples
+// written, 0 for invalid arguments.
+#[no_mangle]
+pub extern "C" fn downmix_to_mono(
+    ptr: *const f32,
+    len: usize,
+    channels: usize,
+    out_ptr: *mut f32,
+) -> usize {
+    guarded_or(0, || {
+        if channels == 0 || ptr.is_null() || out_ptr.is_null() {
+            return 0;
+        }
+        let frames = len / channels;
+        // Raw reads rather than slices, since the buffers may alias. Each mono
+        // sample lands at or before the start of its own frame, so in place every
+        // frame is read before anything overwrites it.
+        for frame in 0..frames {
+            let sum: f32 = (0..channels)
+                .map(|ch| unsafe { ptr.add(frame * channels + ch).read() })
+                .sum();
+            unsafe { out_ptr.add(frame).write(sum / channels as f32) };
+        }
+        frames
+    })
+}
+
+// Zero-lag normalized cross-correlation of interleaved stereo, as shown on a
+// phase meter: +1.0 for identical channels, -1.0 when one is inverted (a
+// wiring fault that cancels on downmix), near 0.0 for unrelated channels.
+// Returns 0.0 if either channel is silent, and NaN for an odd or empty len.
+#[no_mangle]
+pub extern "C" fn stereo_correlation(ptr: *const f32, len: usize) -> f32 {
+    guarded_or(f32::NAN, || {
+        let slice = match input_slice(ptr, len) {
+            Some(slice) if len.is_multiple_of(2) => slice,
+            _ => return f32::NAN,
+        };
+        let (mut lr, mut ll, mut rr) = (0.0f64, 0.0f64, 0.0f64);
+        for frame in slice.chunks_exact(2) {
+            let (l, r) = (frame[0] as f64, frame[1] as f64);
+            lr += l * r;
+            ll += l * l;
+            rr += r * r;
+        }
+        let norm = (ll * rr).sqrt();
+        if norm > 0.0 {
+            (lr / norm).clamp(-1.0, 1.0) as f32
+        } else {
+            0.0
+        }
+    })
+}
+
+// Number of samples resample_linear writes for the given input.
+fn resampled_len(in_len: usize, in_rate: f32, out_rate: f32) -> usize {
+    (in_len as f64 * out_rate as f64 / in_rate as f64).ceil() as usize
+}
+
+// Linear-interpolation resampler. Output sample j is taken at input position
+// j * in_rate / out_rate, so out_ptr must hold ceil(in_len * out_rate /
+// in_rate) samples. Positions past the last input sample hold its value.
+// There is no anti-aliasing filter, so downsampling folds content above the
+// new Nyquist back into the band. Returns the number of samples written, 0
+// for invalid arguments. The output may overlap the input (e.g. resampling
+// in place into a large enough buffer); the input is copied first then.
+#[no_mangle]
+pub extern "C" fn resample_linear(
+    in_ptr: *const f32,
+    in_len: usize,
+    in_rate: f32,
+    out_rate: f32,
+    out_ptr: *mut f32,
+) -> usize {
+    guarded_or(0, || {
+        if !(in_rate > 0.0 && out_rate > 0.0 && in_rate.is_finite() && out_rate.is_finite()) {
+            return 0;
+        }
+        let out_len = resampled_len(in_len, in_rate, out_rate);
+        if in_ptr.is_null() || in_len == 0 || out_ptr.is_null() || out_len == 0 {
+            return 0;
+        }
+        let in_range = in_ptr as usize..in_ptr.wrapping_add(in_len) as usize;
+        let out_range = out_ptr as usize..out_ptr.wrapping_add(out_len) as usize;
+        let input = unsafe { std::slice::from_raw_parts(in_ptr, in_len) };
+        // Overlapping buffers read from a copy, so the output slice is the only
+        // live reference to the shared memory.
+        let copy;
+        let input = if in_range.start < out_range.end && out_range.start < in_range.end {
+            copy = input.to_vec();
+            &copy[..]
+        } else {
+            input
+        };
+        let out = unsafe { std::slice::from_raw_parts_mut(out_ptr, out_len) };
+        let step = in_rate as f64 / out_rate as f64;
+        let last = input.len() - 1;
+        for (j, sample) in out.iter_mut().enumerate() {
+            let position = j as f64 * step;
+            let index = (position.floor() as usize).min(last);
+            let frac = (position - index as f64).clamp(0.0, 1.0) as f32;
+            let next = input[(index + 1).min(last)];
+            *sample = input[index] + (next - input[index]) * frac;
+        }
+        out_len
+    })
+}
+
+// RMS of each consecutive, non-overlapping `window`-sample block, for drawing
+// an activity plot. out_ptr must hold ceil(len / window) values: like
+// process_chunks_batch, a trailing partial block is included and measured over
+// its actual length. The raw samples are used (set_remove_dc does not apply).
+// Returns the number of values written, 0 for invalid arguments.
+#[no_mangle]
+pub extern "C" fn energy_envelope(
+    ptr: *const f32,
+    len: usize,
+    window: usize,
+    out_ptr: *mut f32,
+) -> usize {
+    guarded_or(0, || {
+        if window == 0 {
+            return 0;
+        }
+        let count = len.div_ceil(window);
+        let (slice, out) = match (input_slice(ptr, len), input_slice_mut(out_ptr, count)) {
+            (Some(slice), Some(out)) => (slice, out),
+            _ => return 0,
+        };
+        for (value, block) in out.iter_mut().zip(slice.chunks(window)) {
+            *value = (sum_squares(block) / block.len() as f32).sqrt();
+        }
+        count
+    })
+}
+
+// Copies successive frame_size-sample frames starting every `hop` samples
+// into out_ptr back to back, so overlapping analysis windows (hop <
+// frame_size) can be fed to an FFT in one batch. Produces 1 + (len -
+// frame_size) / hop frames when len >= frame_size; samples after the last
+// whole frame are not copied. out_ptr must hold frames * frame_size values.
+// Returns the number of frames, 0 if len < frame_size or for invalid
+// arguments.
+#[no_mangle]
+pub extern "C" fn frame_overlap(
+    ptr: *const f32,
+    len: usize,
+    frame_size: usize,
+    hop: usize,
+    out_ptr: *mut f32,
+) -> usize {
+    guarded_or(0, || {
+        if frame_size == 0 || hop == 0 || len < frame_size {
+            return 0;
+        }
+        let frames = 1 + (len - frame_size) / hop;
+        let out_len = match frames.checked_mul(frame_size) {
+            Some(out_len) => out_len,
+            None => return 0,
+        };
+        let (slice, out) = match (input_slice(ptr, len), input_slice_mut(out_ptr, out_len)) {
+            (Some(slice), Some(out)) => (slice, out),
+            _ => return 0,
+        };
+        for (i, frame) in out.chunks_exact_mut(frame_size).enumerate() {
+            frame.copy_from_slice(&slice[i * hop..i * hop + frame_size]);
+        }
+        frames
+    })
+}
+
+// Block length for trim_silence's short-term energy (~1.3 ms at 48 kHz).
+const TRIM_WINDOW: usize = 64;
+
+// Finds the span from the first to the last TRIM_WINDOW-sample block whose RMS
+// exceeds threshold, widens it by the set_trim_margin guard on each side
+// (clamped to the buffer) and writes it as [*out_start, *out_end), so the
+// caller can slice the original buffer. A trailing partial block is measured
+// over its actual length. A silent buffer or invalid input gives
+// start == end == 0. Nothing is written if either output pointer is null.
+#[no_mangle]
+pub extern "C" fn trim_silence(
+    ptr: *const f32,
+    len: usize,
+    threshold: f32,
+    out_start: *mut usize,
+    out_end: *mut usize,
+) {
+    guarded_or((), || {
+        let (start_out, end_out) = match unsafe { (out_start.as_mut(), out_end.as_mut()) } {
+            (Some(start), Some(end)) => (start, end),
+            _ => return,
+        };
+        let loud = |block: &[f32]| (sum_squares(block) / block.len() as f32).sqrt() > threshold;
+        let bounds = input_slice(ptr, len).and_then(|slice| {
+            let first = slice.chunks(TRIM_WINDOW).position(loud)?;
+            let last = slice.chunks(TRIM_WINDOW).rposition(loud)?;
+            let margin = TRIM_MARGIN.with(Cell::get);
+            let start = (first * TRIM_WINDOW).saturating_sub(margin);
+            let end = ((last + 1) * TRIM_WINDOW).saturating_add(margin).min(len);
+            Some((start, end))
+        });
+        (*start_out, *end_out) = bounds.unwrap_or((0, 0));
+    })
+}
+
+// Samples of padding trim_silence keeps either side of the detected speech, so
+// soft onsets and decays below the threshold aren't cut. 0 by default.
+#[no_mangle]
+pub extern "C" fn set_trim_margin(samples: usize) {
+    TRIM_MARGIN.with(|margin| margin.set(samples));
+}
+
+// Mean sample value, 0.0 for empty input.
+#[no_mangle]
+pub extern "C" fn dc_offset(ptr: *const f32, len: usize) -> f32 {
+    guarded_or(f32::NAN, || match input_slice(ptr, len) {
+        Some(slice) => mean(slice.iter().copied()),
+        None => 0.0,
+    })
+}
+
+// Subtracts the buffer's mean from every sample in place.
+#[no_mangle]
+pub extern "C" fn remove_dc_offset(ptr: *mut f32, len: usize) {
+    guarded_or((), || {
+        let slice = match input_slice_mut(ptr, len) {
+            Some(slice) => slice,
+            None => return,
+        };
+        let offset = mean(slice.iter().copied());
+        for sample in slice.iter_mut() {
+            *sample -= offset;
+        }
+    })
+}
+
+// Multiplies every sample by gain in place.
+#[no_mangle]
+pub extern "C" fn apply_gain(ptr: *mut f32, len: usize, gain: f32) {
+    guarded_or((), || {
+        if let Some(slice) = input_slice_mut(ptr, len) {
+            for sample in slice.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    })
+}
+
+// Scales the buffer in place so its peak magnitude equals target_peak and
+// returns the gain applied. Silent or empty buffers are left alone (gain 1.0).
+// Results are clamped to +/-target_peak so rounding can't overshoot it.
+#[no_mangle]
+pub extern "C" fn normalize_peak(ptr: *mut f32, len: usize, target_peak: f32) -> f32 {
+    guarded_or(f32::NAN, || {
+        let slice = match input_slice_mut(ptr, len) {
+            Some(slice) => slice,
+            None => return 1.0,
+        };
+        let peak = slice.iter().fold(0.0, |peak: f32, s| peak.max(s.abs()));
+        if peak <= 0.0 {
+            return 1.0;
+        }
+        let target = target_peak.abs();
+        let gain = target / peak;
+        for sample in slice.iter_mut() {
+            *sample = (*sample * gain).clamp(-target, target);
+        }
+        gain
+    })
+}
+
+// Rebuilds each run of same-sign samples with abs(sample) >= threshold from a
+// cubic Hermite curve between the samples either side of it, using the slopes
+// going into and out of the run, so the flattened peak is replaced by a
+// rounded one. Results are clamped to +/-1.0. Both anchors must be unclipped,
+// so runs touching either end of the buffer or directly next to a clipped
+// sample of the other sign are left alone. The threshold defaults as in
+// count_clipped_samples. Returns the number of samples rewritten.
+#[no_mangle]
+pub extern "C" fn declip(ptr: *mut f32, len: usize, threshold: f32) -> usize {
+    guarded_or(0, || {
+        let threshold = if threshold > 0.0 {
+            threshold
+        } else {
+            CLIP_SAMPLE_LEVEL
+        };
+        let slice = match input_slice_mut(ptr, len) {
+            Some(slice) => slice,
+            None => return 0,
+        };
+        let clipped = |s: f32| s.abs() >= threshold;
+
+        let mut repaired = 0;
+        let mut start = 0;
+        while start < slice.len() {
+            if !clipped(slice[start]) {
+                start += 1;
+                continue;
+            }
+            let sign = slice[start].signum();
+            let mut end = start;
+            while end < slice.len() && clipped(slice[end]) && slice[end].signum() == sign {
+                end += 1;
+            }
+            if start == 0 || end == slice.len() || clipped(slice[start - 1]) || clipped(slice[end])
+            {
+                start = end;
+                continue;
+            }
+
+            // Anchors at start - 1 and end, one sample per unit of t * span.
+            let (before, after) = (start - 1, end);
+            let span = (after - before) as f32;
+            let (p0, p1) = (slice[before], slice[after]);
+            let m0 = if before > 0 {
+                p0 - slice[before - 1]
+            } else {
+                0.0
+            } * span;
+            let m1 = if after + 1 < slice.len() {
+                slice[after + 1] - p1
+            } else {
+                0.0
+            } * span;
+            for (offset, sample) in slice[start..end].iter_mut().enumerate() {
+                let t = (offset + 1) as f32 / span;
+                let (t2, t3) = (t * t, t * t * t);
+                let value = (2.0 * t3 - 3.0 * t2 + 1.0) * p0
+                    + (t3 - 2.0 * t2 + t) * m0
+                    + (-2.0 * t3 + 3.0 * t2) * p1
+                    + (t3 - t2) * m1;
+                *sample = value.clamp(-1.0, 1.0);
+            }
+            repaired += end - start;
+            start = end;
+        }
+        repaired
+    })
+}
+
+// Soft limiter for clip prevention, in place. Samples within +/-threshold pass
+// through unchanged; above it the excess is compressed along a tanh curve,
+//   t + (1 - t) * tanh((|x| - t) / (1 - t)),
+// which meets the linear part with matching slope and approaches (never
+// exceeds) 1.0. threshold is clamped to 0.0..=1.0 (NaN counts as 0.0); at 1.0
+// this degrades to a hard clip at +/-1.0.
+#[no_mangle]
+pub extern "C" fn soft_limit(ptr: *mut f32, len: usize, threshold: f32) {
+    guarded_or((), || {
+        let slice = match input_slice_mut(ptr, len) {
+            Some(slice) => slice,
+            None => return,
+        };
+        let threshold = unit_coefficient(threshold);
+        let knee = 1.0 - threshold;
+        for sample in slice.iter_mut() {
+            let magnitude = sample.abs();
+            if magnitude <= threshold {
+                continue;
+            }
+            let limited = if knee > 0.0 {
+                threshold + knee * ((magnitude - threshold) / knee).tanh()
+            } else {
+                1.0
+            };
+            *sample = limited.min(1.0).copysign(*sample);
+        }
+    })
+}
+
+// Writes out[i] = a[i] * wa(t) + b[i] * wb(t) for t = i / len. Works through
+// raw pointers so out may be the same buffer as a or b (an in-place fade).
+fn crossfade_with(
+    a_ptr: *const f32,
+    b_ptr: *const f32,
+    len: usize,
+    out_ptr: *mut f32,
+    weights: impl Fn(f32) -> (f32, f32),
+) {
+    if a_ptr.is_null() || b_ptr.is_null() || out_ptr.is_null() {
+        return;
+    }
+    for i in 0..len {
+        let (wa, wb) = weights(i as f32 / len as f32);
+        unsafe {
+            let mixed = a_ptr.add(i).read() * wa + b_ptr.add(i).read() * wb;
+            out_ptr.add(i).write(mixed);
+        }
+    }
+}
+
+// Linear crossfade from a to b over len samples into out_ptr.
+#[no_mangle]
+pub extern "C" fn crossfade(a_ptr: *const f32, b_ptr: *const f32, len: usize, out_ptr: *mut f32) {
+    guarded_or((), || {
+        crossfade_with(a_ptr, b_ptr, len, out_ptr, |t| (1.0 - t, t));
+    })
+}
+
+// Equal-power crossfade using sqrt weights, so the summed power of two
+// uncorrelated signals stays constant and the splice doesn't dip in loudness.
+#[no_mangle]
+pub extern "C" fn crossfade_equal_power(
+    a_ptr: *const f32,
+    b_ptr: *const f32,
+    len: usize,
+    out_ptr: *mut f32,
+) {
+    guarded_or((), || {
+        crossfade_with(a_ptr, b_ptr, len, out_ptr, |t| ((1.0 - t).sqrt(), t.sqrt()));
+    })
+}
+
+// Per-sample decay of the gate's peak envelope follower (a time constant of
+// about 1000 samples, ~23 ms at 44.1 kHz), long enough that zero crossings
+// inside a tone don't close the gate.
+const GATE_ENVELOPE_DECAY: f32 = 0.999;
+
+fn unit_coefficient(value: f32) -> f32 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    }
+}
+
+// In-place noise gate: samples whose short-term peak envelope is below
+// threshold are attenuated to silence. attack and release (clamped to
+// 0.0..=1.0) are per-sample smoothing coefficients for the gate opening and
+// closing: 0.0 switches instantly, values near 1.0 fade slowly. The gate
+// starts closed.
+#[no_mangle]
+pub extern "C" fn noise_gate(ptr: *mut f32, len: usize, threshold: f32, attack: f32, release: f32) {
+    guarded_or((), || {
+        let slice = match input_slice_mut(ptr, len) {
+            Some(slice) => slice,
+            None => return,
+        };
+        let attack = unit_coefficient(attack);
+        let release = unit_coefficient(release);
+
+        let mut envelope: f32 = 0.0;
+        let mut gain: f32 = 0.0;
+        for sample in slice.iter_mut() {
+            envelope = sample.abs().max(envelope * GATE_ENVELOPE_DECAY);
+            let target = if envelope >= threshold { 1.0 } else { 0.0 };
+            let smoothing = if target > gain { attack } else { release };
+            gain = target + smoothing * (gain - target);
+            *sample *= gain;
+        }
+    })
+}
+
+// When enabled, every classification and metrics function analyzes the
+// DC-free signal (the caller's buffer is left untouched). Off by default.
+#[no_mangle]
+pub extern "C" fn set_remove_dc(enabled: bool) {
+    REMOVE_DC.with(|flag| flag.set(enabled));
+}
+
+// Overrides the RMS silence floor and clipping ceiling used for classification.
+// Returns 0 on success, or -1 if either value is outside 0.0..=1.0 or
+// silence >= clipping (the current thresholds are left unchanged).
+#[no_mangle]
+pub extern "C" fn set_thresholds(silence: f32, clipping: f32) -> i32 {
+    let range = 0.0..=1.0;
+    if !range.contains(&silence) || !range.contains(&clipping) || silence >= clipping {
+        return INVALID_INPUT;
+    }
+    THRESHOLDS.with(|t| t.set(Thresholds { silence, clipping }));
+    0
+}
+
+// Writes [silence, clipping] into the two floats at out_ptr.
+#[no_mangle]
+pub extern "C" fn get_thresholds(out_ptr: *mut f32) {
+    if out_ptr.is_null() {
+        return;
+    }
+    let limits = thresholds();
+    let out = unsafe { std::slice::from_raw_parts_mut(out_ptr, 2) };
+    out[0] = limits.silence;
+    out[1] = limits.clipping;
+}
+
+// Result of `spectral_analyze`.
+//
+// Layout (little-endian, 12 bytes total):
+//   offset 0: dominant_frequency_hz f32 (strongest non-DC bin)
+//   offset 4: spectral_centroid_hz  f32 (magnitude-weighted mean frequency)
+//   offset 8: spectral_energy       f32 (sum of |X[k]|^2 / n over all n bins,
+//                                        i.e. the frame's sum of squares)
+#[repr(C)]
+pub struct SpectrumResult {
+    pub dominant_frequency_hz: f32,
+    pub spectral_centroid_hz: f32,
+    pub spectral_energy: f32,
+}
+
+// Magnitude-weighted mean frequency of a one-sided power spectrum, 0.0 when
+// it holds no energy.
+fn centroid_hz(power: &[f32], bin_hz: f32) -> f32 {
+    let mut weighted = 0.0;
+    let mut magnitude_sum = 0.0;
+    for (k, p) in power.iter().enumerate() {
+        let magnitude = p.sqrt();
+        weighted += magnitude * k as f32 * bin_hz;
+        magnitude_sum += magnitude;
+    }
+    if magnitude_sum > 0.0 {
+        weighted / magnitude_sum
+    } else {
+        0.0
+    }
+}
+
+fn compute_spectrum(
+    slice: &[f32],
+    sample_rate: f32,
+    window: bool,
+    plan: Option<&FftPlan>,
+) -> SpectrumResult {
+    let (power, n) = fft::power_spectrum(slice, window, plan);
+    let bin_hz = sample_rate / n as f32;
+
+    let dominant_bin = (1..power.len())
+        .max_by(|&a, &b| power[a].total_cmp(&power[b]))
+        .unwrap_or(0);
+
+    // power only covers bins 0..=n/2; the mirrored bins 1..n/2 count twice.
+    let nyquist = n / 2;
+    let one_sided: f32 = power
+        .iter()
+        .enumerate()
+        .map(|(k, p)| if k == 0 || k == nyquist { *p } else { 2.0 * p })
+        .sum();
+
+    SpectrumResult {
+        dominant_frequency_hz: dominant_bin as f32 * bin_hz,
+        spectral_centroid_hz: centroid_hz(&power, bin_hz),
+        spectral_energy: one_sided / n as f32,
+    }
+}
+
+// Zero-pads or truncates the buffer to the nearest power of two and runs an
+// FFT over it, Hann-windowing the samples first when `window` is set (the
+// spectral_energy then reflects the windowed frame). Returns null for empty
+// input or a non-positive sample rate; release the result with
+// free_spectrum_result.
+#[no_mangle]
+pub extern "C" fn spectral_analyze(
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+    window: bool,
+) -> *mut SpectrumResult {
+    spectral_analyze_with_plan(std::ptr::null(), ptr, len, sample_rate, window)
+}
+
+// spectral_analyze using a precomputed FFT plan, which also fixes the
+// transform size: the buffer is zero-padded or truncated to the plan's size
+// instead of the nearest power of two. A null plan behaves like
+// spectral_analyze.
+#[no_mangle]
+pub extern "C" fn spectral_analyze_with_plan(
+    plan: *const FftPlan,
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+    window: bool,
+) -> *mut SpectrumResult {
+    guarded_or(std::ptr::null_mut(), || {
+        let plan = unsafe { plan.as_ref() };
+        match input_slice(ptr, len) {
+            Some(slice) if sample_rate > 0.0 => {
+                Box::into_raw(Box::new(compute_spectrum(slice, sample_rate, window, plan)))
+            }
+            _ => std::ptr::null_mut(),
+        }
+    })
+}
+
+// Spectral centroid ("brightness") in Hz of the Hann-windowed buffer, the
+// same value spectral_analyze reports with windowing on. Returns 0.0 for a
+// silent or empty buffer, or a non-positive sample rate.
+#[no_mangle]
+pub extern "C" fn spectral_centroid(ptr: *const f32, len: usize, sample_rate: f32) -> f32 {
+    spectral_centroid_with_plan(std::ptr::null(), ptr, len, sample_rate)
+}
+
+// spectral_centroid using a precomputed FFT plan (sized as in
+// spectral_analyze_with_plan). A null plan behaves like spectral_centroid.
+#[no_mangle]
+pub extern "C" fn spectral_centroid_with_plan(
+    plan: *const FftPlan,
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+) -> f32 {
+    guarded_or(f32::NAN, || {
+        let plan = unsafe { plan.as_ref() };
+        match input_slice(ptr, len) {
+            Some(slice) if sample_rate > 0.0 => {
+                let (power, n) = fft::power_spectrum(slice, true, plan);
+                centroid_hz(&power, sample_rate / n as f32)
+            }
+            _ => 0.0,
+        }
+    })
+}
+
+// Precomputes twiddle factors for repeated transforms of one size. Returns
+// null unless size is a power of two. Release with destroy_fft_plan.
+#[no_mangle]
+pub extern "C" fn create_fft_plan(size: usize) -> *mut FftPlan {
+    guarded_or(std::ptr::null_mut(), || match FftPlan::new(size) {
+        Some(plan) => Box::into_raw(Box::new(plan)),
+        None => std::ptr::null_mut(),
+    })
+}
+
+// Forward FFT of the plan's size: reads size samples from in_ptr and writes
+// the full complex spectrum (all size bins, unscaled) to out_real_ptr and
+// out_imag_ptr, each of which must hold size values. in_ptr may be either
+// output (e.g. an in-place transform into out_real_ptr), but the two outputs
+// must not overlap. Returns 0, or -1 for a null pointer.
+#[no_mangle]
+pub extern "C" fn fft_plan_forward(
+    plan: *const FftPlan,
+    in_ptr: *const f32,
+    out_real_ptr: *mut f32,
+    out_imag_ptr: *mut f32,
+) -> i32 {
+    guarded(|| {
+        let plan = match unsafe { plan.as_ref() } {
+            Some(plan) => plan,
+            None => return INVALID_INPUT,
+        };
+        if in_ptr.is_null() || out_real_ptr.is_null() || out_imag_ptr.is_null() {
+            return INVALID_INPUT;
+        }
+        let size = plan.size();
+        // Copied before any output slice exists, as the input may alias one.
+        unsafe { std::ptr::copy(in_ptr, out_real_ptr, size) };
+        let re = unsafe { std::slice::from_raw_parts_mut(out_real_ptr, size) };
+        let im = unsafe { std::slice::from_raw_parts_mut(out_imag_ptr, size) };
+        im.fill(0.0);
+        plan.forward(re, im);
+        0
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_fft_plan(plan: *mut FftPlan) {
+    if plan.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(plan);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_spectrum_result(ptr: *mut SpectrumResult) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(ptr);
+    }
+}
+
+// Linear-amplitude gain of the IEC 61672 A-weighting curve, normalized to
+// 1.0 (0 dB) at 1 kHz.
+fn a_weighting_gain(hz: f32) -> f32 {
+    let f2 = (hz as f64).powi(2);
+    let r = 12194.0f64.powi(2) * f2 * f2
+        / ((f2 + 20.6f64.powi(2))
+            * ((f2 + 107.7f64.powi(2)) * (f2 + 737.9f64.powi(2))).sqrt()
+            * (f2 + 12194.0f64.powi(2)));
+    // +2.00 dB brings R_A(1 kHz) up to unity.
+    (r * 10f64.powf(2.0 / 20.0)) as f32
+}
+
+// RMS after A-weighting the Hann-windowed power spectrum, so low-frequency
+// rumble counts for far less than it does in the flat RMS. A 1 kHz tone reads
+// the same as its plain RMS. Returns 0.0 for empty input or a non-positive
+// sample rate.
+#[no_mangle]
+pub extern "C" fn a_weighted_rms(ptr: *const f32, len: usize, sample_rate: f32) -> f32 {
+    a_weighted_rms_with_plan(std::ptr::null(), ptr, len, sample_rate)
+}
+
+// a_weighted_rms using a precomputed FFT plan (sized as in
+// spectral_analyze_with_plan). A null plan behaves like a_weighted_rms.
+#[no_mangle]
+pub extern "C" fn a_weighted_rms_with_plan(
+    plan: *const FftPlan,
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+) -> f32 {
+    guarded_or(f32::NAN, || {
+        let plan = unsafe { plan.as_ref() };
+        let slice = match input_slice(ptr, len) {
+            Some(slice) if sample_rate > 0.0 => slice,
+            _ => return 0.0,
+        };
+        let (power, n) = fft::power_spectrum(slice, true, plan);
+        let bin_hz = sample_rate / n as f32;
+        let nyquist = n / 2;
+
+        let weighted: f64 = power
+            .iter()
+            .enumerate()
+            .map(|(k, p)| {
+                let gain = a_weighting_gain(k as f32 * bin_hz) as f64;
+                let mirrored = if k == 0 || k == nyquist { 1.0 } else { 2.0 };
+                mirrored * gain * gain * *p as f64
+            })
+            .sum();
+
+        // Parseval over the windowed frame, undoing the periodic Hann window's
+        // 3/8 mean-square loss so the level matches an unwindowed RMS.
+        let windowed_len = slice.len().min(n) as f64 * 3.0 / 8.0;
+        (weighted / (n as f64 * windowed_len)).sqrt() as f32
+    })
+}
+
+// Harmonics 2..=THD_HARMONICS count towards estimate_thd.
+const THD_HARMONICS: usize = 5;
+// Bins either side of a harmonic's nominal bin summed into its power; covers
+// the Hann window's main lobe plus an off-bin tone's spread. Narrowed for low
+// fundamentals so neighbouring bands never overlap.
+const THD_BAND_BINS: usize = 3;
+// Below this many bins (e.g. 188 Hz at 48 kHz with 1024 samples) the
+// fundamental's main lobe spills into its harmonics' bands and the estimate
+// is meaningless.
+const THD_MIN_FUNDAMENTAL_BINS: f64 = 4.0;
+// A fundamental holding less than this share of the spectrum's power reads as
+// absent.
+const THD_MIN_FUNDAMENTAL_SHARE: f64 = 1e-6;
+
+// Total harmonic distortion of a calibration tone as an amplitude ratio (not a
+// percentage): sqrt(sum of harmonic powers) / sqrt(fundamental power) over the
+// Hann-windowed spectrum, using harmonics 2..=THD_HARMONICS that fall below
+// Nyquist. Returns 0.0 when the fundamental carries negligible energy, for
+// empty input, when fundamental_hz is not between 0 and Nyquist, or when it
+// is fewer than THD_MIN_FUNDAMENTAL_BINS bins into the transform (use a longer
+// buffer or plan to measure low tones).
+#[no_mangle]
+pub extern "C" fn estimate_thd(
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+    fundamental_hz: f32,
+) -> f32 {
+    estimate_thd_with_plan(std::ptr::null(), ptr, len, sample_rate, fundamental_hz)
+}
+
+// estimate_thd using a precomputed FFT plan (sized as in
+// spectral_analyze_with_plan). A null plan behaves like estimate_thd.
+#[no_mangle]
+pub extern "C" fn estimate_thd_with_plan(
+    plan: *const FftPlan,
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+    fundamental_hz: f32,
+) -> f32 {
+    guarded_or(f32::NAN, || {
+        let plan = unsafe { plan.as_ref() };
+        let slice = match input_slice(ptr, len) {
+            Some(slice) if sample_rate > 0.0 && fundamental_hz > 0.0 => slice,
+            _ => return 0.0,
+        };
+        if fundamental_hz >= sample_rate / 2.0 {
+            return 0.0;
+        }
+        let (power, n) = fft::power_spectrum(slice, true, plan);
+        let bin_hz = sample_rate as f64 / n as f64;
+        let f0_bins = fundamental_hz as f64 / bin_hz;
+        if f0_bins < THD_MIN_FUNDAMENTAL_BINS {
+            return 0.0;
+        }
+        // Adjacent harmonics' rounded centres are at least f0_bins - 1 apart.
+        let half_width = (((f0_bins - 1.0) / 2.0) as usize).min(THD_BAND_BINS);
+        let band_power = |hz: f64| {
+            let center = (hz / bin_hz).round() as usize;
+            let lo = center.saturating_sub(half_width).max(1);
+            let hi = (center + half_width).min(power.len() - 1);
+            power
+                .get(lo..=hi)
+                .map_or(0.0, |band| band.iter().map(|&p| p as f64).sum::<f64>())
+        };
+
+        let fundamental = band_power(fundamental_hz as f64);
+        let total: f64 = power.iter().map(|&p| p as f64).sum();
+        if fundamental <= total * THD_MIN_FUNDAMENTAL_SHARE || fundamental <= 0.0 {
+            return 0.0;
+        }
+        let nyquist = sample_rate as f64 / 2.0;
+        let harmonics: f64 = (2..=THD_HARMONICS)
+            .map(|h| h as f64 * fundamental_hz as f64)
+            .take_while(|&hz| hz < nyquist)
+            .map(band_power)
+            .sum();
+        (harmonics / fundamental).sqrt() as f32
+    })
+}
+
+// HTK mel scale: mel = 2595 * log10(1 + hz / 700).
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+// Floor added before the log so empty mel bands stay finite.
+const LOG_MEL_FLOOR: f32 = 1e-10;
+
+// Writes n_mels log-mel energies for one frame into out_ptr and returns the
+// number written (0 for invalid arguments). The frame is Hann-windowed and
+// transformed at the nearest power-of-two size; its power spectrum is then
+// weighted by n_mels triangular filters whose edges are spaced evenly on the
+// HTK mel scale from 0 Hz to Nyquist, and each band energy is stored as
+// ln(energy + 1e-10).
+#[no_mangle]
+pub extern "C" fn compute_log_mel(
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+    n_mels: usize,
+    out_ptr: *mut f32,
+) -> usize {
+    compute_log_mel_with_plan(std::ptr::null(), ptr, len, sample_rate, n_mels, out_ptr)
+}
+
+// compute_log_mel using a precomputed FFT plan, whose size replaces the
+// nearest power of two as the transform size. A null plan behaves like
+// compute_log_mel.
+#[no_mangle]
+pub extern "C" fn compute_log_mel_with_plan(
+    plan: *const FftPlan,
+    ptr: *const f32,
+    len: usize,
+    sample_rate: f32,
+    n_mels: usize,
+    out_ptr: *mut f32,
+) -> usize {
+    guarded_or(0, || {
+        let plan = unsafe { plan.as_ref() };
+        let (slice, out) = match (input_slice(ptr, len), input_slice_mut(out_ptr, n_mels)) {
+            (Some(slice), Some(out)) if sample_rate > 0.0 => (slice, out),
+            _ => return 0,
+        };
+        let (power, n) = fft::power_spectrum(slice, true, plan);
+        let bin_hz = sample_rate / n as f32;
+
+        let mel_max = hz_to_mel(sample_rate / 2.0);
+        let edges: Vec<f32> = (0..n_mels + 2)
+            .map(|i| mel_to_hz(mel_max * i as f32 / (n_mels + 1) as f32))
+            .collect();
+
+        for (m, band) in out.iter_mut().enumerate() {
+            let (lower, centre, upper) = (edges[m], edges[m + 1], edges[m + 2]);
+            let energy: f32 = power
+                .iter()
+                .enumerate()
+                .map(|(k, p)| {
+                    let hz = k as f32 * bin_hz;
+                    let weight = if hz > lower && hz <= centre {
+                        (hz - lower) / (centre - lower)
+                    } else if hz > centre && hz < upper {
+                        (upper - hz) / (upper - centre)
+                    } else {
+                        0.0
+                    };
+                    weight * p
+                })
+                .sum();
+            *band = (energy + LOG_MEL_FLOOR).ln();
+        }
+        n_mels
+    })
+}
+
+// Multiplies the buffer by a Hann window in place to reduce spectral leakage.
+#[no_mangle]
+pub extern "C" fn apply_hann_window(ptr: *mut f32, len: usize) {
+    guarded_or((), || {
+        if let Some(slice) = input_slice_mut(ptr, len) {
+            fft::hann_window(slice);
+        }
+    })
+}
+
+// Vocal range searched by detect_pitch.
+const PITCH_MIN_HZ: f32 = 60.0;
+const PITCH_MAX_HZ: f32 = 500.0;
+// Normalized autocorrelation a lag must reach for the frame to count as voiced.
+const PITCH_MIN_CORRELATION: f32 = 0.5;
+
+// Fundamental frequency in Hz via normalized autocorrelation over lags
+// covering 60-500 Hz, or 0.0 when the frame is too quiet, unvoiced, or too
+// short to hold two periods of the lowest pitch that fits. Picks the first
+// autocorrelation peak within 90% of the strongest one, which avoids octave
+// errors from peaks at multiples of the period, and refines the lag with
+// parabolic interpolation.
+#[no_mangle]
+pub extern "C" fn detect_pitch(ptr: *const f32, len: usize, sample_rate: f32) -> f32 {
+    guarded_or(f32::NAN, || {
+        let slice = match input_slice(ptr, len) {
+            Some(slice) if sample_rate.is_finite() && sample_rate > 0.0 => slice,
+            _ => return 0.0,
+        };
+        let offset = mean(slice.iter().copied());
+        let samples: Vec<f32> = slice.iter().map(|s| s - offset).collect();
+        if (scalar_sum_squares(&samples) / len as f32).sqrt() < thresholds().silence {
+            return 0.0;
+        }
+
+        // At least 2, so the guard lag below it isn't lag 0 (always 1.0).
+        let min_lag = ((sample_rate / PITCH_MAX_HZ).floor() as usize).max(2);
+        let max_lag = ((sample_rate / PITCH_MIN_HZ).ceil() as usize).min(len / 2);
+        // Huge rates saturate min_lag, so compare before doing arithmetic on it.
+        if max_lag < min_lag {
+            return 0.0;
+        }
+
+        // correlation[i] holds the normalized autocorrelation at lag
+        // min_lag - 1 + i. The guard lag at each end is never a candidate, but
+        // lets a period right at min_lag or max_lag register as a local peak.
+        let correlation: Vec<f32> = (min_lag - 1..=max_lag + 1)
+            .map(|lag| {
+                let (head, tail) = (&samples[..len - lag], &samples[lag..]);
+                let cross: f32 = head.iter().zip(tail).map(|(a, b)| a * b).sum();
+                let energy = (scalar_sum_squares(head) * scalar_sum_squares(tail)).sqrt();
+                if energy > 0.0 {
+                    cross / energy
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        let candidates = 1..correlation.len() - 1;
+        let strongest = correlation[candidates.clone()]
+            .iter()
+            .copied()
+            .fold(f32::MIN, f32::max);
+        if strongest < PITCH_MIN_CORRELATION {
+            return 0.0;
+        }
+        let peak = candidates.into_iter().find(|&i| {
+            correlation[i] >= 0.9 * strongest
+                && correlation[i] >= correlation[i - 1]
+                && correlation[i] >= correlation[i + 1]
+        });
+        let peak = match peak {
+            Some(peak) => peak,
+            None => return 0.0,
+        };
+
+        let (left, centre, right) = (
+            correlation[peak - 1],
+            correlation[peak],
+            correlation[peak + 1],
+        );
+        let curvature = left - 2.0 * centre + right;
+        let shift = if curvature < 0.0 {
+            0.5 * (left - right) / curvature
+        } else {
+            0.0
+        };
+        sample_rate / ((min_lag - 1 + peak) as f32 + shift)
+    })
+}
+
+// Buffers handed to the host carry a header word in front of the returned
+// pointer recording the element count, so dealloc frees exactly what alloc
+// reserved. The `size` argument to the dealloc functions is kept for ABI
+// compatibility but ignored; passing the number of samples written instead of
+// the allocation size is harmless.
+const ALLOC_HEADER: usize = std::mem::size_of::<usize>();
+
+fn buffer_layout<T>(count: usize) -> Option<Layout> {
+    count
+        .checked_mul(std::mem::size_of::<T>())
+        .and_then(|bytes| bytes.checked_add(ALLOC_HEADER))
+        .and_then(|bytes| Layout::from_size_align(bytes, std::mem::align_of::<usize>()).ok())
+}
+
+// Null if the size overflows or the allocator refuses the request.
+fn try_alloc_buffer<T>(count: usize) -> *mut T {
+    let layout = match buffer_layout::<T>(count) {
+        Some(layout) => layout,
+        None => return std::ptr::null_mut(),
+    };
+    unsafe {
+        let base = std::alloc::alloc(layout);
+        if base.is_null() {
+            return std::ptr::null_mut();
+        }
+        (base as *mut usize).write(count);
+        base.add(ALLOC_HEADER) as *mut T
+    }
+}
+
+// Aborts the instance if the allocation fails.
+fn alloc_buffer<T>(count: usize) -> *mut T {
+    let ptr: *mut T = try_alloc_buffer(count);
+    if ptr.is_null() {
+        match buffer_layout::<T>(count) {
+            Some(layout) => std::alloc::handle_alloc_error(layout),
+            None => panic!("allocation size overflow"),
+        }
+    }
+    ptr
+}
+
+// `ptr` must come from alloc_buffer::<T>.
+fn dealloc_buffer<T>(ptr: *mut T) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let base = (ptr as *mut u8).sub(ALLOC_HEADER);
+        let count = (base as *const usize).read();
+        if let Some(layout) = buffer_layout::<T>(count) {
+            std::alloc::dealloc(base, layout);
+        }
+    }
+}
+
+// Aborts the WASM instance if the allocation fails; prefer try_alloc, which
+// reports failure with a null pointer instead.
+#[no_mangle]
+pub extern "C" fn alloc(size: usize) -> *mut f32 {
+    alloc_buffer(size)
+}
+
+// Like alloc, but returns null when `size` samples can't be allocated. Free
+// with dealloc as usual.
+#[no_mangle]
+pub extern "C" fn try_alloc(size: usize) -> *mut f32 {
+    try_alloc_buffer(size)
+}
+
+#[no_mangle]
+pub extern "C" fn dealloc(ptr: *mut f32, _size: usize) {
+    dealloc_buffer(ptr)
+}
+
+#[no_mangle]
+pub extern "C" fn alloc_i16(size: usize) -> *mut i16 {
+    alloc_buffer(size)
+}
+
+#[no_mangle]
+pub extern "C" fn dealloc_i16(ptr: *mut i16, _size: usize) {
+    dealloc_buffer(ptr)
+}
+
+// Fixed-capacity history of the most recent samples, for assembling small
+// WebAudio chunks into analysis windows. When full, new samples overwrite the
+// oldest ones.
+pub struct RingBuffer {
+    data: Box<[f32]>,
+    // Index the next sample is written to.
+    head: usize,
+    filled: usize,
+}
+
+impl RingBuffer {
+    fn push(&mut self, samples: &[f32]) -> usize {
+        let capacity = self.data.len();
+        // Only the last `capacity` samples of an oversized chunk can survive.
+        let kept = &samples[samples.len().saturating_sub(capacity)..];
+        for &sample in kept {
+            self.data[self.head] = sample;
+            self.head = (self.head + 1) % capacity;
+        }
+        self.filled = (self.filled + kept.len()).min(capacity);
+        kept.len()
+    }
+
+    fn read_latest(&self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.filled);
+        let capacity = self.data.len();
+        let start = (self.head + capacity - count) % capacity;
+        // The requested span is at most two contiguous runs of storage.
+        let first = count.min(capacity - start);
+        out[..first].copy_from_slice(&self.data[start..start + first]);
+        out[first..count].copy_from_slice(&self.data[..count - first]);
+        count
+    }
+}
+
+// Returns null for a capacity of 0. Release with destroy_ring_buffer.
+#[no_mangle]
+pub extern "C" fn create_ring_buffer(capacity: usize) -> *mut RingBuffer {
+    guarded_or(std::ptr::null_mut(), || {
+        if capacity == 0 {
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(RingBuffer {
+            data: vec![0.0; capacity].into_boxed_slice(),
+            head: 0,
+            filled: 0,
+        }))
+    })
+}
+
+// Appends the chunk, overwriting the oldest samples once the buffer is full.
+// Every sample is accepted, but a chunk longer than the capacity only keeps
+// its last `capacity` samples. Returns how many samples of the chunk were
+// stored, 0 for invalid input.
+#[no_mangle]
+pub extern "C" fn ring_push(rb: *mut RingBuffer, ptr: *const f32, len: usize) -> usize {
+    guarded_or(0, || {
+        match (unsafe { rb.as_mut() }, input_slice(ptr, len)) {
+            (Some(rb), Some(slice)) => rb.push(slice),
+            _ => 0,
+        }
+    })
+}
+
+// Copies the most recent `count` samples, oldest first, into out_ptr. If fewer
+// have been pushed, copies all of them to the front of out_ptr. Returns the
+// number of samples copied.
+#[no_mangle]
+pub extern "C" fn ring_read_latest(
+    rb: *const RingBuffer,
+    out_ptr: *mut f32,
+    count: usize,
+) -> usize {
+    guarded_or(0, || {
+        match (unsafe { rb.as_ref() }, input_slice_mut(out_ptr, count)) {
+            (Some(rb), Some(out)) => rb.read_latest(out),
+            _ => 0,
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_ring_buffer(rb: *mut RingBuffer) {
+    if rb.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(rb);
+    }
+}
+
+// Session-long scratch space for per-chunk buffers: one allocation up front,
+// after which arena_alloc_f32 is a bump of `used` and arena_reset rewinds it
+// for the next frame. Pointers handed out are invalidated by arena_reset.
+// The storage is held as a raw pointer so handing out a new region never
+// reborrows (and invalidates) the regions the host is still using.
+pub struct Arena {
+    base: *mut f32,
+    capacity: usize,
+    used: usize,
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = Box::from_raw(std::ptr::slice_from_raw_parts_mut(self.base, self.capacity));
+        }
+    }
+}
+
+// Reserves `bytes` of scratch space (rounded down to whole f32s). Returns null
+// if that is less than one f32. Release with destroy_arena.
+#[no_mangle]
+pub extern "C" fn create_arena(bytes: usize) -> *mut Arena {
+    guarded_or(std::ptr::null_mut(), || {
+        let capacity = bytes / std::mem::size_of::<f32>();
+        if capacity == 0 {
+            return std::ptr::null_mut();
+        }
+        let storage = Box::into_raw(vec![0.0f32; capacity].into_boxed_slice());
+        Box::into_raw(Box::new(Arena {
+            base: storage as *mut f32,
+            capacity,
+            used: 0,
+        }))
+    })
+}
+
+// Hands out `count` f32s from the arena, or null if it is exhausted.
+#[no_mangle]
+pub extern "C" fn arena_alloc_f32(arena: *mut Arena, count: usize) -> *mut f32 {
+    guarded_or(std::ptr::null_mut(), || {
+        let arena = match unsafe { arena.as_mut() } {
+            Some(arena) => arena,
+            None => return std::ptr::null_mut(),
+        };
+        match arena.used.checked_add(count) {
+            Some(end) if end <= arena.capacity => {
+                let ptr = unsafe { arena.base.add(arena.used) };
+                arena.used = end;
+                ptr
+            }
+            _ => std::ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn arena_reset(arena: *mut Arena) {
+    if let Some(arena) = unsafe { arena.as_mut() } {
+        arena.used = 0;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn destroy_arena(arena: *mut Arena) {
+    if arena.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = Box::from_raw(arena);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, amplitude: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    // Deterministic uniform noise in [-amplitude, amplitude).
+    fn noise(amplitude: f32, len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                amplitude * ((state >> 8) as f32 / (1u32 << 23) as f32 - 1.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn metrics_report_rms_peak_and_clipping() {
+        let samples = [0.5, -1.0, 0.5, -0.5];
+        let metrics = analyze_audio_chunk(samples.as_ptr(), samples.len());
+        let m = unsafe { &*metrics };
+
+        assert!((m.rms - 0.661_437_8).abs() < 1e-5);
+        assert_eq!(m.peak, 1.0);
+        assert_eq!(m.clipped_sample_count, 1);
+        assert_eq!(
+            m.classification,
+            process_audio_chunk(samples.as_ptr(), samples.len())
+        );
+
+        free_audio_metrics(metrics);
+    }
+
+    #[test]
+    fn empty_buffer_is_invalid() {
+        let samples: [f32; 0] = [];
+        assert_eq!(process_audio_chunk(samples.as_ptr(), 0), INVALID_INPUT);
+
+        let metrics = analyze_audio_chunk(samples.as_ptr(), 0);
+        assert_eq!(unsafe { (*metrics).classification }, INVALID_INPUT);
+        free_audio_metrics(metrics);
+    }
+
+    #[test]
+    fn null_pointer_is_invalid() {
+        assert_eq!(process_audio_chunk(std::ptr::null(), 128), INVALID_INPUT);
+    }
+
+    #[test]
+    fn interleaved_reports_per_channel_rms() {
+        // Left carries a tone, right is silent.
+        let left = sine(440.0, 0.5, 44100.0, 512);
+        let samples: Vec<f32> = left.iter().flat_map(|&l| [l, 0.0]).collect();
+
+        let metrics = analyze_audio_chunk_interleaved(samples.as_ptr(), samples.len(), 2);
+        let m = unsafe { &*metrics };
+        assert_eq!(m.channels, 2);
+        assert!((m.channel_rms[0] - compute_metrics(&left).rms).abs() < 1e-6);
+        assert_eq!(m.channel_rms[1], 0.0);
+        assert_eq!(m.channel_rms[2], 0.0);
+        // The silent channel is worse than the good one.
+        assert_eq!(m.classification, 0);
+        free_interleaved_metrics(metrics);
+    }
+
+    #[test]
+    fn interleaved_clipping_dominates_silence() {
+        let samples: Vec<f32> = (0..256).flat_map(|_| [0.0, 0.95, -0.95]).collect();
+        assert_eq!(
+            process_audio_chunk_interleaved(samples.as_ptr(), samples.len(), 3),
+            2
+        );
+    }
+
+    #[test]
+    fn interleaved_drops_trailing_partial_frame() {
+        // The final lone 1.0 would make the left channel clip if it were counted.
+        let mut samples: Vec<f32> = (0..64).flat_map(|_| [0.1, 0.1]).collect();
+        samples.push(1.0);
+        let metrics = compute_interleaved_metrics(samples.as_ptr(), samples.len(), 2);
+        assert!((metrics.channel_rms[0] - 0.1).abs() < 1e-6);
+        assert_eq!(metrics.classification, 1);
+    }
+
+    #[test]
+    fn interleaved_rejects_bad_channel_counts() {
+        let samples = [0.1f32; 16];
+        assert_eq!(
+            process_audio_chunk_interleaved(samples.as_ptr(), 16, 0),
+            INVALID_INPUT
+        );
+        assert_eq!(
+            process_audio_chunk_interleaved(samples.as_ptr(), 16, 9),
+            INVALID_INPUT
+        );
+        assert_eq!(
+            process_audio_chunk_interleaved(samples.as_ptr(), 1, 2),
+            INVALID_INPUT
+        );
+
+        // Rejected input reports no channels rather than the requested count.
+        let rejected = [
+            (samples.as_ptr(), 16, 0),
+            (samples.as_ptr(), 16, 9),
+            (samples.as_ptr(), 1, 2),
+            (std::ptr::null(), 16, 2),
+        ];
+        for (ptr, len, channels) in rejected {
+            let metrics = analyze_audio_chunk_interleaved(ptr, len, channels);
+            let m = unsafe { &*metrics };
+            assert_eq!((m.channels, m.classification), (0, INVALID_INPUT));
+            assert_eq!(m.channel_rms, [0.0; MAX_CHANNELS]);
+            free_interleaved_metrics(metrics);
+        }
+        let metrics = compute_interleaved_metrics(samples.as_ptr(), 16, 2);
+        assert_eq!(metrics.channels, 2);
+    }
+
+    #[test]
+    fn i16_matches_float_classification() {
+        let signals = [
+            sine(440.0, 0.5, 44100.0, 1024),
+            sine(440.0, 0.001, 44100.0, 1024),
+            vec![0.95; 1024],
+            noise(0.5, 1024, 3),
+        ];
+        for signal in &signals {
+            let pcm: Vec<i16> = signal.iter().map(|&s| (s * 32768.0) as i16).collect();
+            assert_eq!(
+                process_audio_chunk_i16(pcm.as_ptr(), pcm.len()),
+                process_audio_chunk(signal.as_ptr(), signal.len())
+            );
+        }
+    }
+
+    #[test]
+    fn i16_alloc_round_trip() {
+        let ptr = alloc_i16(256);
+        let buf = unsafe { std::slice::from_raw_parts_mut(ptr, 256) };
+        buf.fill(i16::MIN);
+        assert_eq!(process_audio_chunk_i16(ptr, 256), 2);
+        dealloc_i16(ptr, 256);
+        assert_eq!(
+            process_audio_chunk_i16(std::ptr::null(), 256),
+            INVALID_INPUT
+        );
+    }
+
+    #[test]
+    fn dealloc_ignores_mismatched_size() {
+        for _ in 0..64 {
+            let ptr = alloc(4096);
+            let buf = unsafe { std::slice::from_raw_parts_mut(ptr, 4096) };
+            buf.fill(0.5);
+            // Only part of the buffer was "written"; the header still frees all of it.
+            dealloc(ptr, 128);
+        }
+
+        let ptr = alloc(4096);
+        let buf = unsafe { std::slice::from_raw_parts_mut(ptr, 4096) };
+        buf.fill(0.25);
+        assert_eq!(process_audio_chunk(ptr, 4096), 1);
+        dealloc(ptr, 4096);
+        dealloc(std::ptr::null_mut(), 0);
+    }
+
+    #[test]
+    fn try_alloc_returns_null_for_impossible_sizes() {
+        // Overflows the byte count.
+        assert!(try_alloc(usize::MAX).is_null());
+        // Representable, but far beyond any address space.
+        assert!(try_alloc(isize::MAX as usize / 8).is_null());
+
+        let ptr = try_alloc(1024);
+        assert!(!ptr.is_null());
+        dealloc(ptr, 1024);
+    }
+
+    #[test]
+    fn zero_sized_alloc_round_trips() {
+        let ptr = alloc(0);
+        assert!(!ptr.is_null());
+        dealloc(ptr, 0);
+    }
+
+    #[test]
+    fn dbfs_of_full_scale_square_is_zero() {
+        let samples: Vec<f32> = (0..64)
+            .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        assert!(rms_dbfs(samples.as_ptr(), samples.len()).abs() < 1e-5);
+
+        let half = [0.5f32; 64];
+        assert!((rms_dbfs(half.as_ptr(), half.len()) + 6.0206).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dbfs_floors_silence_and_empty_input() {
+        let zeros = [0.0f32; 64];
+        assert_eq!(rms_dbfs(zeros.as_ptr(), zeros.len()), DBFS_FLOOR);
+        assert_eq!(rms_dbfs(zeros.as_ptr(), 0), DBFS_FLOOR);
+        assert_eq!(rms_dbfs(std::ptr::null(), 64), DBFS_FLOOR);
+    }
+
+    #[test]
+    fn tracker_smooths_a_single_quiet_chunk() {
+        let tracker = create_rms_tracker(4);
+        let loud = sine(440.0, 0.5, 44100.0, 256);
+        let quiet = [0.0f32; 256];
+
+        for _ in 0..4 {
+            assert_eq!(tracker_push(tracker, loud.as_ptr(), loud.len()), 1);
+        }
+        // A per-chunk classifier would flip to Silence here.
+        assert_eq!(process_audio_chunk(quiet.as_ptr(), quiet.len()), 0);
+        assert_eq!(tracker_push(tracker, quiet.as_ptr(), quiet.len()), 1);
+
+        // Once the window is all silence the tracker follows.
+        for _ in 0..3 {
+            tracker_push(tracker, quiet.as_ptr(), quiet.len());
+        }
+        assert_eq!(tracker_push(tracker, quiet.as_ptr(), quiet.len()), 0);
+        destroy_rms_tracker(tracker);
+    }
+
+    #[test]
+    fn tracker_rejects_invalid_input() {
+        assert!(create_rms_tracker(0).is_null());
+        let samples = [0.1f32; 8];
+        assert_eq!(
+            tracker_push(std::ptr::null_mut(), samples.as_ptr(), 8),
+            INVALID_INPUT
+        );
+
+        let tracker = create_rms_tracker(2);
+        assert_eq!(tracker_push(tracker, samples.as_ptr(), 0), INVALID_INPUT);
+        destroy_rms_tracker(tracker);
+        destroy_rms_tracker(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn peak_amplitude_takes_the_largest_magnitude() {
+        let samples = [0.1, -0.7, 0.3, 0.65];
+        assert_eq!(peak_amplitude(samples.as_ptr(), samples.len()), 0.7);
+        assert_eq!(peak_amplitude(samples.as_ptr(), 0), 0.0);
+    }
+
+    #[test]
+    fn crest_factor_of_square_sine_and_impulse() {
+        let square: Vec<f32> = (0..1024)
+            .map(|i| if (i / 50) % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        assert!((compute_metrics(&square).crest_factor - 1.0).abs() < 1e-4);
+
+        let tone = sine(441.0, 0.5, 44100.0, 1000);
+        let crest = compute_metrics(&tone).crest_factor;
+        assert!(
+            (crest - std::f32::consts::SQRT_2).abs() < 0.01,
+            "crest = {crest}"
+        );
+
+        let mut impulse = vec![0.0f32; 1024];
+        impulse[100] = 0.8;
+        assert!((compute_metrics(&impulse).crest_factor - 32.0).abs() < 1e-3);
+
+        assert_eq!(compute_metrics(&[0.0; 64]).crest_factor, 0.0);
+    }
+
+    #[test]
+    fn clipped_samples_counts_both_polarities() {
+        let samples = [0.2, 1.0, -1.0, 0.995, -0.5, -0.991, 0.8];
+        let len = samples.len();
+        assert_eq!(count_clipped_samples(samples.as_ptr(), len, 0.99), 4);
+        assert_eq!(count_clipped_samples(samples.as_ptr(), len, 0.8), 5);
+        assert_eq!(count_clipped_samples(samples.as_ptr(), len, 0.0), 4);
+        assert_eq!(
+            count_clipped_samples(samples.as_ptr(), len, 0.0),
+            compute_metrics(&samples).clipped_sample_count as usize
+        );
+        assert_eq!(count_clipped_samples(samples.as_ptr(), 0, 0.5), 0);
+    }
+
+    #[test]
+    fn dc_offset_is_measured_and_removed() {
+        let clean = sine(441.0, 0.3, 44100.0, 1000);
+        let mut biased: Vec<f32> = clean.iter().map(|s| s + 0.5).collect();
+
+        assert!((dc_offset(biased.as_ptr(), biased.len()) - 0.5).abs() < 1e-4);
+
+        remove_dc_offset(biased.as_mut_ptr(), biased.len());
+        assert!(dc_offset(biased.as_ptr(), biased.len()).abs() < 1e-4);
+        let clean_rms = compute_metrics(&clean).rms;
+        assert!((compute_metrics(&biased).rms - clean_rms).abs() < 1e-4);
+    }
+
+    #[test]
+    fn classification_can_ignore_dc_bias() {
+        // A near-silent signal riding on a large bias reads as loud.
+        let biased: Vec<f32> = sine(441.0, 0.005, 44100.0, 1000)
+            .into_iter()
+            .map(|s| s + 0.5)
+            .collect();
+        assert_eq!(process_audio_chunk(biased.as_ptr(), biased.len()), 1);
+
+        set_remove_dc(true);
+        assert_eq!(process_audio_chunk(biased.as_ptr(), biased.len()), 0);
+        assert!((dc_offset(biased.as_ptr(), biased.len()) - 0.5).abs() < 1e-4);
+        set_remove_dc(false);
+    }
+
+    #[test]
+    fn spectrum_finds_a_440hz_tone() {
+        let samples = sine(440.0, 0.5, 44100.0, 4096);
+        let result = spectral_analyze(samples.as_ptr(), samples.len(), 44100.0, false);
+        let r = unsafe { &*result };
+
+        let bin_hz = 44100.0 / 4096.0;
+        assert!((r.dominant_frequency_hz - 440.0).abs() <= bin_hz);
+        let sum_squares: f32 = samples.iter().map(|s| s * s).sum();
+        assert!((r.spectral_energy - sum_squares).abs() / sum_squares < 1e-3);
+        free_spectrum_result(result);
+    }
+
+    #[test]
+    fn spectrum_centroid_of_on_bin_tone_is_exact() {
+        let bin_hz = 44100.0 / 1024.0;
+        let samples = sine(40.0 * bin_hz, 0.5, 44100.0, 1024);
+        let r = compute_spectrum(&samples, 44100.0, false, None);
+        assert!((r.dominant_frequency_hz - 40.0 * bin_hz).abs() < 1e-3);
+        assert!((r.spectral_centroid_hz - 40.0 * bin_hz).abs() < 1.0);
+    }
+
+    #[test]
+    fn hann_window_reduces_leakage_of_an_off_bin_tone() {
+        // 10.5 bins: the worst case for leakage.
+        let bin_hz = 44100.0 / 1024.0;
+        let samples = sine(10.5 * bin_hz, 0.5, 44100.0, 1024);
+
+        // Share of the total power in the peak bin and its two neighbours.
+        let concentration = |window: bool| {
+            let (power, _) = fft::power_spectrum(&samples, window, None);
+            let peak = (1..power.len())
+                .max_by(|&a, &b| power[a].total_cmp(&power[b]))
+                .unwrap();
+            let near: f32 = power[peak - 1..=peak + 1].iter().sum();
+            near / power.iter().sum::<f32>()
+        };
+        let raw = concentration(false);
+        let windowed = concentration(true);
+        assert!(windowed > raw, "windowed {windowed} vs raw {raw}");
+        assert!(windowed > 0.9);
+
+        let mut windowed_copy = samples.clone();
+        apply_hann_window(windowed_copy.as_mut_ptr(), windowed_copy.len());
+        let r = spectral_analyze(samples.as_ptr(), samples.len(), 44100.0, true);
+        let expected = compute_spectrum(&windowed_copy, 44100.0, false, None);
+        assert_eq!(
+            unsafe { (*r).dominant_frequency_hz },
+            expected.dominant_frequency_hz
+        );
+        free_spectrum_result(r);
+    }
+
+    #[test]
+    fn spectrum_rejects_invalid_input() {
+        let samples = [0.1f32; 16];
+        assert!(spectral_analyze(samples.as_ptr(), 0, 44100.0, false).is_null());
+        assert!(spectral_analyze(samples.as_ptr(), 16, 0.0, true).is_null());
+        free_spectrum_result(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn vad_holds_open_through_a_mid_word_dip() {
+        let vad = create_vad(0.05, 0.02, 3);
+        let silence = [0.0f32; 256];
+        let speech = sine(200.0, 0.3, 16000.0, 256);
+        let dip = sine(200.0, 0.01, 16000.0, 256);
+        let push = |chunk: &[f32]| vad_process(vad, chunk.as_ptr(), chunk.len());
+
+        assert_eq!(push(&silence), 0);
+        // Quiet onset stays below open_thresh.
+        assert_eq!(push(&dip), 0);
+        assert_eq!(push(&speech), 1);
+        assert_eq!(push(&speech), 1);
+        // Two quiet chunks are shorter than the hangover.
+        assert_eq!(push(&dip), 1);
+        assert_eq!(push(&dip), 1);
+        assert_eq!(push(&speech), 1);
+        // A sustained tail closes the gate on the third quiet chunk.
+        assert_eq!(push(&silence), 1);
+        assert_eq!(push(&silence), 1);
+        assert_eq!(push(&silence), 0);
+        assert_eq!(push(&silence), 0);
+        destroy_vad(vad);
+    }
+
+    #[test]
+    fn vad_rejects_invalid_configuration() {
+        assert!(create_vad(0.02, 0.05, 3).is_null());
+        assert!(create_vad(0.05, -0.01, 3).is_null());
+        let samples = [0.1f32; 8];
+        assert_eq!(
+            vad_process(std::ptr::null_mut(), samples.as_ptr(), 8),
+            INVALID_INPUT
+        );
+        destroy_vad(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn flatness_separates_tones_from_noise() {
+        let tone = sine(440.0, 0.5, 44100.0, 4096);
+        let tone_flatness = spectral_flatness(tone.as_ptr(), tone.len());
+        assert!(tone_flatness < 0.1, "tone flatness = {tone_flatness}");
+        assert_eq!(process_audio_chunk(tone.as_ptr(), tone.len()), 1);
+
+        let hiss = noise(0.3, 4096, 11);
+        let hiss_flatness = spectral_flatness(hiss.as_ptr(), hiss.len());
+        assert!(hiss_flatness > 0.8, "noise flatness = {hiss_flatness}");
+        assert_eq!(process_audio_chunk(hiss.as_ptr(), hiss.len()), 3);
+    }
+
+    #[test]
+    fn short_noise_chunks_are_classified_noisy() {
+        // A single periodogram of white noise sits right at NOISY_FLATNESS,
+        // so chunks this short need their segments averaged too.
+        for len in [32, 64, 128, 256, 384, 511] {
+            let good = (0..200)
+                .filter(|&seed| {
+                    let hiss = noise(0.3, len, seed);
+                    process_audio_chunk(hiss.as_ptr(), len) != 3
+                })
+                .count();
+            assert_eq!(
+                good, 0,
+                "{good}/200 noise chunks of {len} samples not noisy"
+            );
+
+            for freq in [220.0, 1000.0, 3000.0] {
+                let tone = sine(freq, 0.5, 44100.0, len);
+                assert_eq!(
+                    process_audio_chunk(tone.as_ptr(), len),
+                    1,
+                    "{freq} Hz, {len}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flatness_of_silence_is_zero() {
+        let zeros = [0.0f32; 512];
+        assert_eq!(spectral_flatness(zeros.as_ptr(), zeros.len()), 0.0);
+        assert_eq!(spectral_flatness(zeros.as_ptr(), 0), 0.0);
+    }
+
+    #[test]
+    fn apply_gain_scales_in_place() {
+        let mut samples = [0.1, -0.2, 0.3];
+        apply_gain(samples.as_mut_ptr(), samples.len(), 2.0);
+        assert_eq!(samples, [0.2, -0.4, 0.6]);
+    }
+
+    #[test]
+    fn normalize_peak_doubles_a_half_amplitude_sine() {
+        let original = sine(441.0, 0.5, 44100.0, 1000);
+        let mut samples = original.clone();
+        let gain = normalize_peak(samples.as_mut_ptr(), samples.len(), 1.0);
+
+        assert!((gain - 2.0).abs() < 1e-3, "gain = {gain}");
+        for (out, orig) in samples.iter().zip(&original) {
+            assert!((out - orig * gain).abs() < 1e-6);
+            assert!(out.abs() <= 1.0);
+        }
+        assert!((peak_amplitude(samples.as_ptr(), samples.len()) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn normalize_peak_never_exceeds_target() {
+        let mut samples = noise(0.3, 4096, 5);
+        normalize_peak(samples.as_mut_ptr(), samples.len(), 0.7);
+        assert!(samples.iter().all(|s| s.abs() <= 0.7));
+    }
+
+    #[test]
+    fn normalize_peak_leaves_silence_alone() {
+        let mut zeros = [0.0f32; 32];
+        assert_eq!(normalize_peak(zeros.as_mut_ptr(), zeros.len(), 1.0), 1.0);
+        assert!(zeros.iter().all(|&s| s == 0.0));
+        assert_eq!(normalize_peak(std::ptr::null_mut(), 32, 1.0), 1.0);
+    }
+
+    #[test]
+    fn batch_classifies_exact_multiple() {
+        let mut samples = vec![0.0f32; 256];
+        samples.extend(sine(440.0, 0.5, 44100.0, 256));
+        samples.extend(vec![0.95f32; 256]);
+        let mut out = [9i32; 3];
+
+        let count = process_chunks_batch(samples.as_ptr(), samples.len(), 256, out.as_mut_ptr());
+        assert_eq!(count, 3);
+        assert_eq!(out, [0, 1, 2]);
+    }
+
+    #[test]
+    fn batch_classifies_trailing_partial_chunk() {
+        let mut samples = sine(440.0, 0.5, 44100.0, 512);
+        samples.extend(vec![0.0f32; 100]);
+        let mut out = [9i32; 4];
+
+        let count = process_chunks_batch(samples.as_ptr(), samples.len(), 256, out.as_mut_ptr());
+        assert_eq!(count, 3);
+        let tail = &samples[512..];
+        assert_eq!(
+            out[..3],
+            [1, 1, process_audio_chunk(tail.as_ptr(), tail.len())]
+        );
+        assert_eq!(out[2], 0);
+        assert_eq!(out[3], 9);
+    }
+
+    #[test]
+    fn batch_rejects_invalid_arguments() {
+        let samples = [0.1f32; 16];
+        let mut out = [0i32; 4];
+        assert_eq!(
+            process_chunks_batch(samples.as_ptr(), 16, 0, out.as_mut_ptr()),
+            0
+        );
+        assert_eq!(
+            process_chunks_batch(samples.as_ptr(), 16, 4, std::ptr::null_mut()),
+            0
+        );
+        assert_eq!(
+            process_chunks_batch(std::ptr::null(), 16, 4, out.as_mut_ptr()),
+            0
+        );
+    }
+
+    #[test]
+    fn slice_stats_match_the_iterator_loop() {
+        for len in [1, 3, 4, 5, 63, 64, 1001] {
+            let samples: Vec<f32> = noise(1.2, len, len as u32)
+                .iter()
+                .map(|s| s + 0.1)
+                .collect();
+            for remove_dc in [false, true] {
+                set_remove_dc(remove_dc);
+                let from_slice = compute_metrics(&samples);
+                let from_iter = compute_metrics_from(samples.iter().copied(), Flatness::Always);
+                set_remove_dc(false);
+                assert!((from_slice.rms - from_iter.rms).abs() < 1e-6, "len {len}");
+                assert_eq!(from_slice.peak, from_iter.peak);
+                assert_eq!(
+                    from_slice.clipped_sample_count,
+                    from_iter.clipped_sample_count
+                );
+                assert_eq!(from_slice.zcr, from_iter.zcr);
+            }
+        }
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    #[test]
+    fn simd_sum_squares_matches_scalar() {
+        for len in [1, 2, 3, 4, 5, 7, 8, 63, 64, 65, 1000, 4097] {
+            let samples = noise(0.8, len, len as u32);
+            let simd = (simd_sum_squares(&samples) / len as f32).sqrt();
+            let scalar = (scalar_sum_squares(&samples) / len as f32).sqrt();
+            assert!((simd - scalar).abs() < 1e-5, "len {len}");
+        }
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    #[test]
+    fn simd_stats_match_scalar() {
+        for len in [1, 2, 3, 4, 5, 7, 8, 9, 63, 64, 65, 1000, 4097] {
+            let mut samples = noise(1.2, len, len as u32);
+            samples[len / 2] = f32::NAN;
+            for offset in [0.0, 0.1] {
+                let simd = simd_stats(&samples, offset);
+                let scalar = scalar_stats(samples.iter().map(|s| s - offset));
+                assert_eq!(simd.len, scalar.len);
+                assert_eq!(simd.peak, scalar.peak, "len {len}");
+                assert_eq!(simd.clipped, scalar.clipped, "len {len}");
+                assert_eq!(simd.zero_crossings, scalar.zero_crossings, "len {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn classification_codes_are_stable() {
+        assert_eq!(Classification::Silence as i32, 0);
+        assert_eq!(Classification::Good as i32, 1);
+        assert_eq!(Classification::Clipping as i32, 2);
+        assert_eq!(Classification::Noisy as i32, 3);
+        assert_eq!(INVALID_INPUT, -1);
+        for code in 0..4 {
+            assert_eq!(
+                Classification::from_code(code).map(|c| c as i32),
+                Some(code)
+            );
+        }
+    }
+
+    #[test]
+    fn classification_names_are_null_terminated() {
+        let name = |code| unsafe { CStr::from_ptr(classification_name(code) as *const _) };
+        assert_eq!(name(0).to_str(), Ok("Silence"));
+        assert_eq!(name(1).to_str(), Ok("Good"));
+        assert_eq!(name(2).to_str(), Ok("Clipping"));
+        assert_eq!(name(3).to_str(), Ok("Noisy"));
+        assert_eq!(name(-1).to_str(), Ok("Invalid input"));
+        assert_eq!(name(-99).to_str(), Ok("Internal error"));
+        assert_eq!(name(42).to_str(), Ok("Unknown"));
+    }
+
+    #[test]
+    fn pitch_of_a_220hz_sawtooth() {
+        let sample_rate = 44100.0;
+        let samples: Vec<f32> = (0..4096)
+            .map(|i| {
+                let phase = (220.0 * i as f32 / sample_rate).fract();
+                0.5 * (2.0 * phase - 1.0)
+            })
+            .collect();
+        let pitch = detect_pitch(samples.as_ptr(), samples.len(), sample_rate);
+        assert!((pitch - 220.0).abs() < 3.0, "pitch = {pitch}");
+    }
+
+    #[test]
+    fn pitch_of_noise_and_silence_is_zero() {
+        let hiss = noise(0.3, 4096, 21);
+        assert_eq!(detect_pitch(hiss.as_ptr(), hiss.len(), 44100.0), 0.0);
+
+        let zeros = [0.0f32; 4096];
+        assert_eq!(detect_pitch(zeros.as_ptr(), zeros.len(), 44100.0), 0.0);
+        assert_eq!(detect_pitch(zeros.as_ptr(), 0, 44100.0), 0.0);
+    }
+
+    #[test]
+    fn pitch_at_the_top_of_the_range_is_not_an_octave_low() {
+        for (hz, sample_rate) in [
+            (499.0, 44100.0),
+            (500.0, 48000.0),
+            (498.0, 16000.0),
+            (490.0, 8000.0),
+            (61.0, 48000.0),
+        ] {
+            let tone = sine(hz, 0.5, sample_rate, 4096);
+            let pitch = detect_pitch(tone.as_ptr(), tone.len(), sample_rate);
+            assert!(
+                (pitch - hz).abs() < hz * 0.02,
+                "{hz} Hz at {sample_rate}: {pitch}"
+            );
+        }
+    }
+
+    #[test]
+    fn pitch_rejects_unusable_sample_rates() {
+        let tone = sine(220.0, 0.5, 44100.0, 4096);
+        for rate in [f32::INFINITY, f32::NAN, 1e30, 0.0, -44100.0] {
+            assert_eq!(
+                detect_pitch(tone.as_ptr(), tone.len(), rate),
+                0.0,
+                "rate {rate}"
+            );
+        }
+    }
+
+    #[test]
+    fn downmix_works_in_place() {
+        let left = sine(440.0, 0.8, 44100.0, 256);
+        let right = noise(0.3, 256, 4);
+        let mut samples: Vec<f32> = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(&l, &r)| [l, r])
+            .collect();
+
+        let ptr = samples.as_mut_ptr();
+        assert_eq!(downmix_to_mono(ptr, samples.len(), 2, ptr), 256);
+        for ((m, l), r) in samples.iter().zip(&left).zip(&right) {
+            assert!((m - (l + r) / 2.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn downmix_halves_a_channel_paired_with_silence() {
+        let left = sine(440.0, 0.8, 44100.0, 256);
+        let samples: Vec<f32> = left.iter().flat_map(|&l| [l, 0.0]).collect();
+        let mut mono = vec![0.0f32; 256];
+
+        let written = downmix_to_mono(samples.as_ptr(), samples.len(), 2, mono.as_mut_ptr());
+        assert_eq!(written, 256);
+        for (m, l) in mono.iter().zip(&left) {
+            assert!((m - l / 2.0).abs() < 1e-7);
+        }
+    }
+
+    #[test]
+    fn downmix_ignores_partial_frames_and_bad_arguments() {
+        let samples = [0.2, 0.4, 0.6, 0.8, 1.0];
+        let mut mono = [9.0f32; 3];
+        assert_eq!(
+            downmix_to_mono(samples.as_ptr(), 5, 2, mono.as_mut_ptr()),
+            2
+        );
+        assert!((mono[0] - 0.3).abs() < 1e-6 && (mono[1] - 0.7).abs() < 1e-6);
+        assert_eq!(mono[2], 9.0);
+
+        assert_eq!(
+            downmix_to_mono(samples.as_ptr(), 5, 0, mono.as_mut_ptr()),
+            0
+        );
+        assert_eq!(
+            downmix_to_mono(samples.as_ptr(), 1, 2, mono.as_mut_ptr()),
+            0
+        );
+        assert_eq!(
+            downmix_to_mono(samples.as_ptr(), 5, 2, std::ptr::null_mut()),
+            0
+        );
+    }
+
+    #[test]
+    fn arena_bumps_until_exhausted() {
+        let arena = create_arena(64 * 4);
+        let a = arena_alloc_f32(arena, 40);
+        let b = arena_alloc_f32(arena, 24);
+        assert!(!a.is_null() && !b.is_null());
+        assert_eq!(unsafe { a.add(40) }, b);
+        assert!(arena_alloc_f32(arena, 1).is_null());
+
+        // Usable as ordinary sample buffers.
+        unsafe { std::slice::from_raw_parts_mut(b, 24) }.fill(0.95);
+        assert_eq!(process_audio_chunk(b, 24), 2);
+        destroy_arena(arena);
+    }
+
+    #[test]
+    fn arena_reset_reclaims_space() {
+        let arena = create_arena(100 * 4);
+        let first = arena_alloc_f32(arena, 100);
+        assert!(arena_alloc_f32(arena, 100).is_null());
+
+        arena_reset(arena);
+        assert_eq!(arena_alloc_f32(arena, 100), first);
+        destroy_arena(arena);
+    }
+
+    #[test]
+    fn arena_rejects_invalid_requests() {
+        assert!(create_arena(3).is_null());
+        assert!(arena_alloc_f32(std::ptr::null_mut(), 1).is_null());
+        let arena = create_arena(16);
+        assert!(arena_alloc_f32(arena, 5).is_null());
+        assert!(arena_alloc_f32(arena, usize::MAX).is_null());
+        arena_reset(std::ptr::null_mut());
+        destroy_arena(arena);
+        destroy_arena(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn snr_of_loud_chunk_over_a_quiet_floor() {
+        let est = create_snr_estimator();
+        let hiss = noise(0.002, 512, 9);
+        let speech = sine(300.0, 0.5, 16000.0, 512);
+
+        for _ in 0..10 {
+            let snr = snr_push(est, hiss.as_ptr(), hiss.len());
+            assert!(snr.abs() < 1.0, "snr = {snr}");
+        }
+        let snr = snr_push(est, speech.as_ptr(), speech.len());
+        assert!(snr > 40.0, "snr = {snr}");
+
+        // Sustained speech must not raise the floor.
+        for _ in 0..50 {
+            snr_push(est, speech.as_ptr(), speech.len());
+        }
+        let later = snr_push(est, speech.as_ptr(), speech.len());
+        assert!((later - snr).abs() < 0.1, "later = {later}");
+        destroy_snr_estimator(est);
+    }
+
+    #[test]
+    fn snr_floor_falls_quickly_and_rises_slowly() {
+        let mut est = SnrEstimator {
+            noise_floor: None,
+            recent: VecDeque::new(),
+        };
+        est.push(1e-4);
+        est.push(1e-6);
+        // Half way down in one step.
+        assert!((est.noise_floor.unwrap() - 0.505e-4).abs() < 1e-8);
+
+        let mut est = SnrEstimator {
+            noise_floor: Some(1e-6),
+            recent: VecDeque::new(),
+        };
+        est.push(2e-6);
+        assert!((est.noise_floor.unwrap() - 1.02e-6).abs() < 1e-10);
+    }
+
+    #[test]
+    fn snr_floor_follows_noise_after_digital_silence() {
+        let est = create_snr_estimator();
+        let zeros = [0.0f32; 512];
+        let hiss = noise(0.01, 512, 12);
+        for _ in 0..3 {
+            snr_push(est, zeros.as_ptr(), zeros.len());
+        }
+        let first = snr_push(est, hiss.as_ptr(), hiss.len());
+        assert!(first > 60.0, "first = {first}");
+        let mut snr = first;
+        for _ in 0..2000 {
+            snr = snr_push(est, hiss.as_ptr(), hiss.len());
+        }
+        assert!(snr.abs() < 1.0, "snr = {snr}");
+        destroy_snr_estimator(est);
+    }
+
+    #[test]
+    fn snr_floor_follows_a_sustained_noise_step() {
+        let est = create_snr_estimator();
+        let quiet = noise(0.002, 512, 13);
+        let loud = noise(0.02, 512, 14);
+        for _ in 0..50 {
+            snr_push(est, quiet.as_ptr(), quiet.len());
+        }
+        // At first the step reads as signal, 20 dB over the floor.
+        let step = snr_push(est, loud.as_ptr(), loud.len());
+        assert!((step - 20.0).abs() < 1.0, "step = {step}");
+        let mut snr = step;
+        for _ in 0..1000 {
+            snr = snr_push(est, loud.as_ptr(), loud.len());
+        }
+        assert!(snr.abs() < 1.0, "snr = {snr}");
+        destroy_snr_estimator(est);
+    }
+
+    #[test]
+    fn snr_rejects_invalid_input() {
+        let samples = [0.1f32; 8];
+        assert_eq!(snr_push(std::ptr::null_mut(), samples.as_ptr(), 8), 0.0);
+        let est = create_snr_estimator();
+        assert_eq!(snr_push(est, samples.as_ptr(), 0), 0.0);
+        destroy_snr_estimator(est);
+        destroy_snr_estimator(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn noise_gate_silences_quiet_and_keeps_loud() {
+        let mut signal = sine(440.0, 0.01, 44100.0, 4000);
+        signal.extend(sine(440.0, 0.5, 44100.0, 4000));
+        let original = signal.clone();
+
+        noise_gate(signal.as_mut_ptr(), signal.len(), 0.1, 0.5, 0.9);
+        assert!(signal[..4000].iter().all(|s| s.abs() < 1e-6));
+        // After a short fade-in the loud part passes through untouched.
+        for (out, orig) in signal[4100..].iter().zip(&original[4100..]) {
+            assert!((out - orig).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn noise_gate_with_zero_coefficients_hard_gates() {
+        let mut signal = vec![0.05f32; 100];
+        signal.extend(vec![0.5f32; 100]);
+
+        noise_gate(signal.as_mut_ptr(), signal.len(), 0.1, 0.0, -3.0);
+        assert!(signal[..100].iter().all(|&s| s == 0.0));
+        assert!(signal[100..].iter().all(|&s| s == 0.5));
+    }
+
+    #[test]
+    fn histogram_of_a_ramp_is_even() {
+        let ramp: Vec<f32> = (0..1000).map(|i| i as f32 / 1000.0).collect();
+        let mut counts = [0u32; 10];
+        amplitude_histogram(ramp.as_ptr(), ramp.len(), 10, counts.as_mut_ptr());
+        for count in counts {
+            assert!((99..=101).contains(&count), "{counts:?}");
+        }
+        assert_eq!(counts.iter().sum::<u32>(), 1000);
+    }
+
+    #[test]
+    fn histogram_puts_full_scale_in_the_last_bin() {
+        let samples = [1.0, -1.0, 1.5, 0.0, -0.3];
+        let mut counts = [7u32; 4];
+        amplitude_histogram(samples.as_ptr(), samples.len(), 4, counts.as_mut_ptr());
+        assert_eq!(counts, [1, 1, 0, 3]);
+
+        amplitude_histogram(samples.as_ptr(), 0, 4, counts.as_mut_ptr());
+        assert_eq!(counts, [0; 4]);
+    }
+
+    #[test]
+    fn crossfade_with_itself_is_identity() {
+        let a = sine(440.0, 0.5, 44100.0, 256);
+        let mut out = vec![0.0f32; 256];
+        crossfade(a.as_ptr(), a.as_ptr(), a.len(), out.as_mut_ptr());
+        for (o, s) in out.iter().zip(&a) {
+            assert!((o - s).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn crossfade_from_one_to_zero_is_a_descending_ramp() {
+        let ones = [1.0f32; 8];
+        let zeros = [0.0f32; 8];
+        let mut out = [0.0f32; 8];
+        crossfade(ones.as_ptr(), zeros.as_ptr(), 8, out.as_mut_ptr());
+        let expected: Vec<f32> = (0..8).map(|i| 1.0 - i as f32 / 8.0).collect();
+        assert_eq!(out.to_vec(), expected);
+    }
+
+    #[test]
+    fn equal_power_crossfade_keeps_power_constant() {
+        let ones = [1.0f32; 16];
+        let zeros = [0.0f32; 16];
+        let mut fade_out = [0.0f32; 16];
+        let mut fade_in = [0.0f32; 16];
+        crossfade_equal_power(ones.as_ptr(), zeros.as_ptr(), 16, fade_out.as_mut_ptr());
+        crossfade_equal_power(zeros.as_ptr(), ones.as_ptr(), 16, fade_in.as_mut_ptr());
+        for (o, i) in fade_out.iter().zip(&fade_in) {
+            assert!((o * o + i * i - 1.0).abs() < 1e-6);
+        }
+        assert!(fade_out.windows(2).all(|w| w[1] < w[0]));
+    }
+
+    #[test]
+    fn crossfade_can_write_in_place() {
+        let mut a = vec![1.0f32; 4];
+        let b = [0.0f32; 4];
+        let ptr = a.as_mut_ptr();
+        crossfade(ptr, b.as_ptr(), 4, ptr);
+        assert_eq!(a, [1.0, 0.75, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn log_mel_concentrates_a_tone_in_its_band() {
+        let sample_rate = 16000.0;
+        let samples = sine(1000.0, 0.5, sample_rate, 2048);
+        let mut mels = [0.0f32; 40];
+        let written = compute_log_mel(
+            samples.as_ptr(),
+            samples.len(),
+            sample_rate,
+            40,
+            mels.as_mut_ptr(),
+        );
+        assert_eq!(written, 40);
+
+        let loudest = (0..40)
+            .max_by(|&a, &b| mels[a].total_cmp(&mels[b]))
+            .unwrap();
+        let mel_max = hz_to_mel(sample_rate / 2.0);
+        let band = |edge: usize| mel_to_hz(mel_max * edge as f32 / 41.0);
+        // The loudest filter's triangle spans the tone.
+        assert!(
+            band(loudest) < 1000.0 && 1000.0 < band(loudest + 2),
+            "band {loudest}"
+        );
+        // Distant bands sit far below it.
+        assert!(mels[loudest] - mels[0] > 10.0);
+        assert!(mels[loudest] - mels[39] > 10.0);
+    }
+
+    #[test]
+    fn mel_scale_round_trips() {
+        assert!((hz_to_mel(1000.0) - 1000.0).abs() < 0.5);
+        for hz in [0.0, 440.0, 8000.0] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn log_mel_rejects_invalid_arguments() {
+        let samples = [0.1f32; 64];
+        let mut mels = [0.0f32; 8];
+        assert_eq!(
+            compute_log_mel(samples.as_ptr(), 64, 16000.0, 0, mels.as_mut_ptr()),
+            0
+        );
+        assert_eq!(
+            compute_log_mel(samples.as_ptr(), 64, 0.0, 8, mels.as_mut_ptr()),
+            0
+        );
+        assert_eq!(
+            compute_log_mel(samples.as_ptr(), 64, 16000.0, 8, std::ptr::null_mut()),
+            0
+        );
+    }
+
+    #[test]
+    fn debouncer_ignores_alternating_chunks() {
+        let deb = create_classifier_debouncer(3);
+        let good = sine(440.0, 0.5, 44100.0, 256);
+        let clipping = [0.95f32; 256];
+        let push = |chunk: &[f32]| debouncer_push(deb, chunk.as_ptr(), chunk.len());
+
+        assert_eq!(push(&good), 1);
+        for _ in 0..10 {
+            assert_eq!(push(&clipping), 1);
+            assert_eq!(push(&good), 1);
+        }
+        // A run of three clipping chunks finally flips the output.
+        assert_eq!(push(&clipping), 1);
+        assert_eq!(push(&clipping), 1);
+        assert_eq!(push(&clipping), 2);
+        assert_eq!(push(&good), 2);
+        destroy_classifier_debouncer(deb);
+    }
+
+    #[test]
+    fn debouncer_rejects_invalid_input() {
+        assert!(create_classifier_debouncer(0).is_null());
+        let samples = [0.1f32; 8];
+        assert_eq!(
+            debouncer_push(std::ptr::null_mut(), samples.as_ptr(), 8),
+            INVALID_INPUT
+        );
+        let deb = create_classifier_debouncer(2);
+        assert_eq!(debouncer_push(deb, samples.as_ptr(), 0), INVALID_INPUT);
+        destroy_classifier_debouncer(deb);
+        destroy_classifier_debouncer(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn guarded_converts_a_panic_into_an_error_code() {
+        let samples = [0.1f32; 4];
+        let out_of_bounds = || {
+            let index = samples.len() + process_audio_chunk(samples.as_ptr(), 4) as usize;
+            samples[index] as i32
+        };
+        assert_eq!(guarded(out_of_bounds), PANICKED);
+        assert_eq!(PANICKED, -99);
+
+        // The module keeps working afterwards.
+        assert_eq!(process_audio_chunk(samples.as_ptr(), samples.len()), 1);
+        assert_eq!(guarded(|| 7), 7);
+        assert!(guarded_or(f32::NAN, || samples[samples.len() + 1]).is_nan());
+    }
+
+    #[test]
+    fn exports_return_their_fallback_when_the_body_panics() {
+        // A capacity this large overflows inside Vec and panics rather than
+        // reaching the allocator.
+        assert!(create_ring_buffer(usize::MAX).is_null());
+        assert!(create_rms_tracker(usize::MAX).is_null());
+
+        let rb = create_ring_buffer(4);
+        let samples = [0.5f32; 3];
+        assert_eq!(ring_push(rb, samples.as_ptr(), samples.len()), 3);
+        destroy_ring_buffer(rb);
+    }
+
+    #[test]
+    fn audio_range_classifies_only_the_selected_samples() {
+        let mut buffer = vec![0.0f32; 2048];
+        buffer[1024..].copy_from_slice(&sine(440.0, 0.3, 48000.0, 1024));
+        let ptr = buffer.as_ptr();
+
+        assert_eq!(process_audio_range(ptr, 2048, 0, 1024), 0);
+        assert_eq!(process_audio_range(ptr, 2048, 512, 1024), 1);
+        // A range ending exactly at len is still in bounds.
+        assert_eq!(process_audio_range(ptr, 2048, 1024, 1024), 1);
+
+        assert_eq!(process_audio_range(ptr, 2048, 1025, 1024), INVALID_INPUT);
+        assert_eq!(process_audio_range(ptr, 2048, usize::MAX, 2), INVALID_INPUT);
+        assert_eq!(process_audio_range(ptr, 2048, 100, 0), INVALID_INPUT);
+        assert_eq!(
+            process_audio_range(std::ptr::null(), 2048, 0, 1),
+            INVALID_INPUT
+        );
+    }
+
+    #[test]
+    fn a_weighting_attenuates_low_frequency_rumble() {
+        assert!((a_weighting_gain(1000.0) - 1.0).abs() < 1e-3);
+
+        let low = sine(50.0, 0.5, 48000.0, 4096);
+        let mid = sine(1000.0, 0.5, 48000.0, 4096);
+        let low_rms = a_weighted_rms(low.as_ptr(), low.len(), 48000.0);
+        let mid_rms = a_weighted_rms(mid.as_ptr(), mid.len(), 48000.0);
+
+        // Equal flat RMS, but 50 Hz sits about 30 dB down the curve.
+        assert!((mid_rms - 0.5 / 2f32.sqrt()).abs() < 0.02, "{mid_rms}");
+        assert!(low_rms < mid_rms * 0.1, "{low_rms} vs {mid_rms}");
+
+        assert_eq!(a_weighted_rms(std::ptr::null(), 0, 48000.0), 0.0);
+        assert_eq!(a_weighted_rms(mid.as_ptr(), mid.len(), 0.0), 0.0);
+    }
+
+    #[test]
+    fn loudness_meter_integrates_a_calibrated_tone() {
+        assert!(create_loudness_meter(0.0).is_null());
+        assert!(create_loudness_meter(f32::NAN).is_null());
+
+        let meter = create_loudness_meter(48000.0);
+        assert!(!meter.is_null());
+        assert_eq!(loudness_integrated(meter), DBFS_FLOOR);
+
+        // A 1 kHz sine at 0.1 peak is -23 LUFS: K-weighting adds ~0.69 dB at
+        // 1 kHz, which the -0.691 offset cancels.
+        let tone = sine(1000.0, 0.1, 48000.0, 48000).repeat(5);
+        for chunk in tone.chunks(1000) {
+            assert_eq!(loudness_push(meter, chunk.as_ptr(), chunk.len()), 0);
+        }
+        let lufs = loudness_integrated(meter);
+        assert!((lufs + 23.0).abs() < 0.1, "{lufs}");
+
+        // Silence falls below the absolute gate and a -43 LUFS passage below
+        // the relative one, so only the few blocks straddling the tone's end
+        // pull the integrated value down slightly.
+        let silence = vec![0.0f32; 48000 * 3];
+        let quiet = sine(1000.0, 0.01, 48000.0, 48000 * 3);
+        loudness_push(meter, silence.as_ptr(), silence.len());
+        loudness_push(meter, quiet.as_ptr(), quiet.len());
+        let gated = loudness_integrated(meter);
+        assert!(gated < lufs && gated > -23.3, "{gated}");
+
+        assert_eq!(loudness_push(meter, std::ptr::null(), 0), INVALID_INPUT);
+        assert_eq!(
+            loudness_push(std::ptr::null_mut(), tone.as_ptr(), 1),
+            INVALID_INPUT
+        );
+        destroy_loudness_meter(meter);
+        destroy_loudness_meter(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn declip_rounds_off_flattened_peaks() {
+        // Four whole periods, so the buffer starts and ends unclipped.
+        let original = sine(500.0, 1.0, 48000.0, 384);
+        let clipped: Vec<f32> = original.iter().map(|s| s.clamp(-0.8, 0.8)).collect();
+        let mut samples = clipped.clone();
+
+        let flat = clipped.iter().filter(|s| s.abs() >= 0.8).count();
+        assert_eq!(declip(samples.as_mut_ptr(), samples.len(), 0.8), flat);
+
+        for ((out, before), orig) in samples.iter().zip(&clipped).zip(&original) {
+            if before.abs() < 0.8 {
+                assert_eq!(out, before);
+            } else {
+                assert!(out.abs() <= 1.0);
+                assert!((out - orig).abs() < 0.1, "{out} vs {orig}");
+            }
+        }
+        // The first repaired peak now rises above the clip level and back.
+        let peak = &samples[10..40];
+        let top = peak.iter().cloned().fold(0.0, f32::max);
+        assert!(top > 0.9, "top = {top}");
+        assert!(peak.windows(2).any(|w| w[1] < w[0]));
+
+        assert_eq!(declip(std::ptr::null_mut(), 4, 0.8), 0);
+    }
+
+    #[test]
+    fn declip_leaves_runs_without_two_unclipped_anchors() {
+        let mut leading = [0.95, 0.95, 0.95, 0.5, 0.2, 0.0];
+        assert_eq!(declip(leading.as_mut_ptr(), leading.len(), 0.9), 0);
+        assert_eq!(leading, [0.95, 0.95, 0.95, 0.5, 0.2, 0.0]);
+
+        // A clipped 16 kHz tone at 48 kHz swings straight from one rail to the
+        // other; neither half may serve as the other's anchor.
+        let mut swing = [0.0, 0.5, -0.95, 0.95, 0.5, 0.0];
+        assert_eq!(declip(swing.as_mut_ptr(), swing.len(), 0.9), 0);
+        assert_eq!(swing, [0.0, 0.5, -0.95, 0.95, 0.5, 0.0]);
+
+        // An isolated run between them is still repaired.
+        let mut mixed = [0.95, 0.3, 0.6, 0.95, 0.95, 0.6, 0.3, -0.95];
+        assert_eq!(declip(mixed.as_mut_ptr(), mixed.len(), 0.9), 2);
+        assert_eq!((mixed[0], mixed[7]), (0.95, -0.95));
+        assert!(mixed[3] > 0.6 && mixed[4] > 0.6);
+    }
+
+    // Splits a flat JSON object of numbers, strings and nulls into key/value
+    // pairs (string values keep their quotes). Enough to check
+    // analyze_audio_chunk_json without a JSON dependency.
+    fn parse_flat_json(json: &str) -> Vec<(String, String)> {
+        let body = json
+            .strip_prefix('{')
+            .and_then(|rest| rest.strip_suffix('}'))
+            .expect("not an object");
+        body.split(',')
+            .map(|field| {
+                let (key, value) = field.split_once(':').expect("missing colon");
+                let key = key.strip_prefix('"').and_then(|k| k.strip_suffix('"'));
+                (key.expect("unquoted key").to_string(), value.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn metrics_json_matches_computed_metrics() {
+        let samples = sine(440.0, 0.5, 44100.0, 1024);
+        let raw = analyze_audio_chunk_json(samples.as_ptr(), samples.len());
+        let json = unsafe { CStr::from_ptr(raw as *const c_char) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        free_json_string(raw);
+
+        let metrics = compute_metrics(&samples);
+        let fields = parse_flat_json(&json);
+        let keys: Vec<&str> = fields.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "rms",
+                "peak",
+                "classification",
+                "classification_code",
+                "clipped_sample_count",
+                "zcr",
+                "crest_factor",
+                "spectral_flatness"
+            ]
+        );
+        let number = |i: usize| fields[i].1.parse::<f32>().unwrap();
+        assert_eq!(number(0), metrics.rms);
+        assert_eq!(number(1), metrics.peak);
+        assert_eq!(fields[2].1, "\"Good\"");
+        assert_eq!(number(3), 1.0);
+        assert_eq!(number(4), metrics.clipped_sample_count as f32);
+        assert_eq!(number(5), metrics.zcr);
+        assert_eq!(number(6), metrics.crest_factor);
+        assert_eq!(number(7), metrics.spectral_flatness);
+    }
+
+    #[test]
+    fn metrics_json_reports_invalid_input_and_non_finite_values() {
+        let raw = analyze_audio_chunk_json(std::ptr::null(), 0);
+        let json = unsafe { CStr::from_ptr(raw as *const c_char) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        free_json_string(raw);
+        assert!(json.contains("\"classification\":\"Invalid input\",\"classification_code\":-1"));
+
+        let mut metrics = compute_metrics(&[0.5; 16]);
+        metrics.crest_factor = f32::NAN;
+        assert!(metrics_json(&metrics).contains("\"crest_factor\":null"));
+        free_json_string(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn resample_at_the_same_rate_is_the_identity() {
+        let input = noise(0.5, 441, 9);
+        let mut out = vec![0.0f32; 441];
+        assert_eq!(
+            resample_linear(input.as_ptr(), 441, 44100.0, 44100.0, out.as_mut_ptr()),
+            441
+        );
+        assert_eq!(out, input);
+    }
+
+    #[test]
+    fn resample_in_place_matches_a_separate_buffer() {
+        let ramp: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let mut expected = vec![0.0f32; 300];
+        resample_linear(ramp.as_ptr(), 100, 16000.0, 48000.0, expected.as_mut_ptr());
+
+        let mut buffer = ramp.clone();
+        buffer.resize(300, 0.0);
+        let ptr = buffer.as_mut_ptr();
+        assert_eq!(resample_linear(ptr, 100, 16000.0, 48000.0, ptr), 300);
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn resample_halves_and_doubles_a_ramp() {
+        let ramp: Vec<f32> = (0..100).map(|i| i as f32).collect();
+
+        let mut down = vec![0.0f32; 50];
+        assert_eq!(resampled_len(100, 32000.0, 16000.0), 50);
+        assert_eq!(
+            resample_linear(ramp.as_ptr(), 100, 32000.0, 16000.0, down.as_mut_ptr()),
+            50
+        );
+        assert_eq!(down[0], 0.0);
+        assert!((99.0 - down[49]).abs() <= 1.0, "last = {}", down[49]);
+        assert!(down.windows(2).all(|w| (w[1] - w[0] - 2.0).abs() < 1e-4));
+
+        let mut up = vec![0.0f32; 300];
+        assert_eq!(
+            resample_linear(ramp.as_ptr(), 100, 16000.0, 48000.0, up.as_mut_ptr()),
+            300
+        );
+        assert!((up[1] - 1.0 / 3.0).abs() < 1e-5);
+        assert!((up[297] - 99.0).abs() < 1e-4 && up[299] == 99.0);
+
+        // 44.1 kHz to 16 kHz needs the ceil'd length.
+        assert_eq!(resampled_len(441, 44100.0, 16000.0), 160);
+        assert_eq!(resampled_len(100, 44100.0, 16000.0), 37);
+        assert_eq!(
+            resample_linear(ramp.as_ptr(), 100, 0.0, 16000.0, up.as_mut_ptr()),
+            0
+        );
+        assert_eq!(
+            resample_linear(ramp.as_ptr(), 100, 16000.0, 48000.0, std::ptr::null_mut()),
+            0
+        );
+    }
+
+    #[test]
+    fn energy_envelope_follows_a_burst() {
+        let mut samples = vec![0.0f32; 1000];
+        samples[300..600].copy_from_slice(&sine(1000.0, 0.5, 48000.0, 300));
+        samples.extend([0.4f32; 50]);
+        let mut out = vec![-1.0f32; 11];
+
+        assert_eq!(
+            energy_envelope(samples.as_ptr(), samples.len(), 100, out.as_mut_ptr()),
+            11
+        );
+        for (i, &value) in out[..10].iter().enumerate() {
+            if (3..6).contains(&i) {
+                assert!(
+                    (value - 0.5 / 2f32.sqrt()).abs() < 0.02,
+                    "window {i}: {value}"
+                );
+            } else {
+                assert_eq!(value, 0.0, "window {i}");
+            }
+        }
+        // The 50-sample tail is measured on its own.
+        assert!((out[10] - 0.4).abs() < 1e-6);
+
+        assert_eq!(
+            energy_envelope(samples.as_ptr(), samples.len(), 0, out.as_mut_ptr()),
+            0
+        );
+        assert_eq!(
+            energy_envelope(std::ptr::null(), 0, 100, out.as_mut_ptr()),
+            0
+        );
+    }
+
+    #[test]
+    fn trim_silence_encloses_the_speech() {
+        let speech = (1000, 3000);
+        let mut samples = noise(0.001, 4100, 4);
+        samples[speech.0..speech.1].copy_from_slice(&sine(220.0, 0.3, 48000.0, 2000));
+        let (mut start, mut end) = (usize::MAX, usize::MAX);
+
+        trim_silence(samples.as_ptr(), samples.len(), 0.01, &mut start, &mut end);
+        assert!(
+            start <= speech.0 && speech.0 - start < TRIM_WINDOW,
+            "start = {start}"
+        );
+        assert!(
+            end >= speech.1 && end - speech.1 < TRIM_WINDOW,
+            "end = {end}"
+        );
+
+        // Speech running into the final partial block keeps the true length.
+        samples[4090..].fill(0.5);
+        trim_silence(samples.as_ptr(), samples.len(), 0.01, &mut start, &mut end);
+        assert_eq!(end, samples.len());
+
+        let silent = noise(0.001, 4096, 4);
+        trim_silence(silent.as_ptr(), silent.len(), 0.01, &mut start, &mut end);
+        assert_eq!((start, end), (0, 0));
+
+        (start, end) = (7, 7);
+        trim_silence(std::ptr::null(), 0, 0.01, &mut start, &mut end);
+        assert_eq!((start, end), (0, 0));
+        trim_silence(
+            samples.as_ptr(),
+            samples.len(),
+            0.01,
+            std::ptr::null_mut(),
+            &mut end,
+        );
+    }
+
+    #[test]
+    fn trim_margin_pads_the_bounds_within_the_buffer() {
+        let mut samples = vec![0.0f32; 4096];
+        samples[1024..2048].copy_from_slice(&sine(220.0, 0.3, 48000.0, 1024));
+        let (mut start, mut end) = (0, 0);
+
+        set_trim_margin(100);
+        trim_silence(samples.as_ptr(), samples.len(), 0.01, &mut start, &mut end);
+        assert_eq!((start, end), (924, 2148));
+
+        // The padding stops at the ends of the buffer.
+        set_trim_margin(5000);
+        trim_silence(samples.as_ptr(), samples.len(), 0.01, &mut start, &mut end);
+        assert_eq!((start, end), (0, 4096));
+
+        set_trim_margin(0);
+        trim_silence(samples.as_ptr(), samples.len(), 0.01, &mut start, &mut end);
+        assert_eq!((start, end), (1024, 2048));
+    }
+
+    #[test]
+    fn silence_timer_fires_once_the_silence_lasts_long_enough() {
+        assert!(create_silence_timer(0.0, 500.0).is_null());
+        assert!(create_silence_timer(16000.0, f32::NAN).is_null());
+        assert!(create_silence_timer(f32::INFINITY, 500.0).is_null());
+        assert!(create_silence_timer(16000.0, f32::INFINITY).is_null());
+        assert!(create_silence_timer(16000.0, 0.0).is_null());
+
+        // 1600 samples at 16 kHz is 100 ms per chunk.
+        let timer = create_silence_timer(16000.0, 500.0);
+        let quiet = vec![0.0f32; 1600];
+        let speech = sine(220.0, 0.3, 16000.0, 1600);
+        let push = |chunk: &[f32]| silence_timer_push(timer, chunk.as_ptr(), chunk.len());
+
+        for _ in 0..4 {
+            assert_eq!(push(&quiet), 0);
+        }
+        assert_eq!(push(&quiet[..1599]), 0); // 499.9 ms
+        assert_eq!(push(&quiet[..1]), 1); // 500 ms
+        assert_eq!(push(&quiet), 1);
+
+        // Speech restarts the count.
+        assert_eq!(push(&speech), 0);
+        for _ in 0..4 {
+            assert_eq!(push(&quiet), 0);
+        }
+        assert_eq!(push(&quiet), 1);
+
+        // A chunk that isn't silent never reports silence, however short the
+        // target.
+        let brief = create_silence_timer(16000.0, 0.001);
+        assert_eq!(silence_timer_push(brief, quiet.as_ptr(), 1), 1);
+        assert_eq!(silence_timer_push(brief, speech.as_ptr(), speech.len()), 0);
+        destroy_silence_timer(brief);
+
+        assert_eq!(
+            silence_timer_push(timer, std::ptr::null(), 0),
+            INVALID_INPUT
+        );
+        destroy_silence_timer(timer);
+        destroy_silence_timer(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn thd_is_near_zero_for_a_clean_sine_and_grows_with_clipping() {
+        let clean = sine(1000.0, 0.8, 48000.0, 4096);
+        let thd = estimate_thd(clean.as_ptr(), clean.len(), 48000.0, 1000.0);
+        assert!(thd < 1e-3, "clean thd = {thd}");
+
+        // Hard clipping a full-scale sine at half amplitude mostly adds odd
+        // harmonics, about 23% of the fundamental through the 5th.
+        let clipped: Vec<f32> = sine(1000.0, 1.0, 48000.0, 4096)
+            .iter()
+            .map(|s| s.clamp(-0.5, 0.5))
+            .collect();
+        let thd = estimate_thd(clipped.as_ptr(), clipped.len(), 48000.0, 1000.0);
+        assert!(thd > 0.15 && thd < 0.4, "clipped thd = {thd}");
+
+        let silence = [0.0f32; 4096];
+        assert_eq!(
+            estimate_thd(silence.as_ptr(), silence.len(), 48000.0, 1000.0),
+            0.0
+        );
+        assert_eq!(
+            estimate_thd(clean.as_ptr(), clean.len(), 48000.0, 24000.0),
+            0.0
+        );
+        assert_eq!(estimate_thd(clean.as_ptr(), clean.len(), 0.0, 1000.0), 0.0);
+    }
+
+    #[test]
+    fn thd_of_low_clean_tones_stays_near_zero() {
+        // 200 Hz is 4.3 bins into a 1024-sample frame at 48 kHz, so the bands
+        // have to narrow to stay clear of each other.
+        for (hz, len) in [(200.0, 1024), (100.0, 2048), (50.0, 4096), (300.0, 1024)] {
+            let clean = sine(hz, 0.8, 48000.0, len);
+            let thd = estimate_thd(clean.as_ptr(), len, 48000.0, hz);
+            assert!(thd < 0.01, "{hz} Hz over {len}: thd = {thd}");
+
+            let clipped: Vec<f32> = sine(hz, 1.0, 48000.0, len)
+                .iter()
+                .map(|s| s.clamp(-0.5, 0.5))
+                .collect();
+            let thd = estimate_thd(clipped.as_ptr(), len, 48000.0, hz);
+            assert!(
+                thd > 0.15 && thd < 0.4,
+                "{hz} Hz over {len}: clipped thd = {thd}"
+            );
+        }
+
+        // Too close to DC to resolve from its harmonics.
+        let hum = sine(50.0, 0.8, 48000.0, 1024);
+        assert_eq!(estimate_thd(hum.as_ptr(), hum.len(), 48000.0, 50.0), 0.0);
+    }
+
+    #[test]
+    fn spectral_centroid_tracks_brightness() {
+        let low = sine(200.0, 0.5, 48000.0, 2048);
+        let high = sine(8000.0, 0.5, 48000.0, 2048);
+        let hiss = noise(0.5, 2048, 11);
+        let centroid = |s: &[f32]| spectral_centroid(s.as_ptr(), s.len(), 48000.0);
+
+        assert!(centroid(&low) < 500.0, "low = {}", centroid(&low));
+        assert!(
+            (centroid(&high) - 8000.0).abs() < 500.0,
+            "high = {}",
+            centroid(&high)
+        );
+        // Flat magnitudes put white noise near the middle of 0..24 kHz.
+        assert!(
+            (centroid(&hiss) - 12000.0).abs() < 1500.0,
+            "noise = {}",
+            centroid(&hiss)
+        );
+
+        let result = spectral_analyze(high.as_ptr(), high.len(), 48000.0, true);
+        assert_eq!(unsafe { (*result).spectral_centroid_hz }, centroid(&high));
+        free_spectrum_result(result);
+
+        assert_eq!(centroid(&[0.0; 512]), 0.0);
+        assert_eq!(spectral_centroid(high.as_ptr(), high.len(), 0.0), 0.0);
+        assert_eq!(spectral_centroid(std::ptr::null(), 0, 48000.0), 0.0);
+    }
+
+    #[test]
+    fn ring_buffer_wraps_and_keeps_the_latest_samples() {
+        assert!(create_ring_buffer(0).is_null());
+        let rb = create_ring_buffer(8);
+        let push = |samples: &[f32]| ring_push(rb, samples.as_ptr(), samples.len());
+        let mut out = [0.0f32; 8];
+
+        assert_eq!(push(&[1.0, 2.0, 3.0, 4.0, 5.0]), 5);
+        assert_eq!(push(&[6.0, 7.0, 8.0, 9.0, 10.0]), 5);
+        // 9 and 10 wrapped around over 1 and 2.
+        assert_eq!(ring_read_latest(rb, out.as_mut_ptr(), 8), 8);
+        assert_eq!(out, [3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0]);
+        assert_eq!(ring_read_latest(rb, out.as_mut_ptr(), 3), 3);
+        assert_eq!(out[..3], [8.0, 9.0, 10.0]);
+
+        // An oversized chunk only keeps its tail.
+        let long: Vec<f32> = (0..20).map(|i| i as f32).collect();
+        assert_eq!(push(&long), 8);
+        assert_eq!(ring_read_latest(rb, out.as_mut_ptr(), 8), 8);
+        assert_eq!(out, [12.0, 13.0, 14.0, 15.0, 16.0, 17.0, 18.0, 19.0]);
+
+        assert_eq!(push(&[]), 0);
+        assert_eq!(ring_read_latest(rb, std::ptr::null_mut(), 8), 0);
+        destroy_ring_buffer(rb);
+        destroy_ring_buffer(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn ring_buffer_partial_reads_before_it_fills() {
+        let rb = create_ring_buffer(1024);
+        let chunk = sine(440.0, 0.5, 48000.0, 128);
+        assert_eq!(ring_push(rb, chunk.as_ptr(), chunk.len()), 128);
+
+        let mut out = vec![-1.0f32; 1024];
+        assert_eq!(ring_read_latest(rb, out.as_mut_ptr(), 1024), 128);
+        assert_eq!(out[..128], chunk[..]);
+        assert!(out[128..].iter().all(|&s| s == -1.0));
+        destroy_ring_buffer(rb);
+    }
+
+    #[test]
+    fn stereo_correlation_flags_inverted_channels() {
+        let left = sine(440.0, 0.5, 48000.0, 1024);
+        let interleave = |right: &[f32]| -> Vec<f32> {
+            left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect()
+        };
+        let correlation = |stereo: &[f32]| stereo_correlation(stereo.as_ptr(), stereo.len());
+
+        assert!((correlation(&interleave(&left)) - 1.0).abs() < 1e-6);
+        let inverted: Vec<f32> = left.iter().map(|s| -s).collect();
+        assert!((correlation(&interleave(&inverted)) + 1.0).abs() < 1e-6);
+
+        let a = noise(0.5, 4096, 21);
+        let b = noise(0.5, 4096, 77);
+        let unrelated: Vec<f32> = a.iter().zip(&b).flat_map(|(&l, &r)| [l, r]).collect();
+        assert!(
+            correlation(&unrelated).abs() < 0.1,
+            "{}",
+            correlation(&unrelated)
+        );
+
+        let one_silent: Vec<f32> = left.iter().flat_map(|&l| [l, 0.0]).collect();
+        assert_eq!(correlation(&one_silent), 0.0);
+        assert!(stereo_correlation(left.as_ptr(), 1023).is_nan());
+        assert!(stereo_correlation(std::ptr::null(), 0).is_nan());
+    }
+
+    #[test]
+    fn smoother_approaches_a_step_geometrically() {
+        let sm = create_smoother(0.25);
+        let mut previous = 0.0;
+        for n in 1..=40 {
+            let out = smoother_push(sm, 1.0);
+            let expected = 1.0 - 0.75f32.powi(n);
+            assert!(
+                (out - expected).abs() < 1e-5,
+                "push {n}: {out} vs {expected}"
+            );
+            assert!(out > previous && out <= 1.0);
+            previous = out;
+        }
+        assert!(1.0 - previous < 1e-4);
+        destroy_smoother(sm);
+
+        // Out-of-range alphas clamp to the ends of the range.
+        let instant = create_smoother(3.0);
+        assert_eq!(smoother_push(instant, 0.7), 0.7);
+        let frozen = create_smoother(f32::NAN);
+        assert_eq!(smoother_push(frozen, 0.7), 0.0);
+        destroy_smoother(instant);
+        destroy_smoother(frozen);
+        assert_eq!(smoother_push(std::ptr::null_mut(), 0.7), 0.0);
+        destroy_smoother(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn fft_plan_forward_matches_a_naive_dft() {
+        assert!(create_fft_plan(0).is_null());
+        assert!(create_fft_plan(100).is_null());
+
+        let plan = create_fft_plan(16);
+        let input: Vec<f32> = (0..16).map(|i| ((i * 7) % 5) as f32 - 2.0).collect();
+        let (mut re, mut im) = ([9.0f32; 16], [9.0f32; 16]);
+        assert_eq!(
+            fft_plan_forward(plan, input.as_ptr(), re.as_mut_ptr(), im.as_mut_ptr()),
+            0
+        );
+        for k in 0..16 {
+            let (mut dft_re, mut dft_im) = (0.0f32, 0.0f32);
+            for (t, &x) in input.iter().enumerate() {
+                let angle = -2.0 * std::f32::consts::PI * (k * t) as f32 / 16.0;
+                dft_re += x * angle.cos();
+                dft_im += x * angle.sin();
+            }
+            assert!(
+                (re[k] - dft_re).abs() < 1e-4 && (im[k] - dft_im).abs() < 1e-4,
+                "bin {k}"
+            );
+        }
+
+        // The input may double as the real output.
+        let (expected_re, expected_im) = (re, im);
+        let mut in_place: [f32; 16] = input.clone().try_into().unwrap();
+        let ptr = in_place.as_mut_ptr();
+        assert_eq!(fft_plan_forward(plan, ptr, ptr, im.as_mut_ptr()), 0);
+        assert_eq!((in_place, im), (expected_re, expected_im));
+
+        let null = std::ptr::null_mut();
+        assert_eq!(
+            fft_plan_forward(plan, input.as_ptr(), null, im.as_mut_ptr()),
+            INVALID_INPUT
+        );
+        assert_eq!(
+            fft_plan_forward(
+                std::ptr::null(),
+                input.as_ptr(),
+                re.as_mut_ptr(),
+                im.as_mut_ptr()
+            ),
+            INVALID_INPUT
+        );
+        destroy_fft_plan(plan);
+        destroy_fft_plan(std::ptr::null_mut());
+    }
+
+    #[test]
+    fn planned_spectral_functions_match_the_unplanned_ones() {
+        let samples = sine(1000.0, 0.5, 48000.0, 1024);
+        let plan = create_fft_plan(1024);
+
+        let planned = spectral_analyze_with_plan(plan, samples.as_ptr(), 1024, 48000.0, true);
+        let unplanned = spectral_analyze(samples.as_ptr(), 1024, 48000.0, true);
+        let (planned_r, unplanned_r) = unsafe { (&*planned, &*unplanned) };
+        assert_eq!(
+            planned_r.dominant_frequency_hz,
+            unplanned_r.dominant_frequency_hz
+        );
+        assert!((planned_r.spectral_centroid_hz - unplanned_r.spectral_centroid_hz).abs() < 0.5);
+        assert!((planned_r.spectral_energy - unplanned_r.spectral_energy).abs() < 1e-3);
+        free_spectrum_result(planned);
+        free_spectrum_result(unplanned);
+
+        let centroid = spectral_centroid_with_plan(plan, samples.as_ptr(), 1024, 48000.0);
+        assert!((centroid - spectral_centroid(samples.as_ptr(), 1024, 48000.0)).abs() < 0.5);
+        assert_eq!(
+            spectral_centroid_with_plan(std::ptr::null(), samples.as_ptr(), 1024, 48000.0),
+            spectral_centroid(samples.as_ptr(), 1024, 48000.0)
+        );
+
+        let a_weighted = a_weighted_rms_with_plan(plan, samples.as_ptr(), 1024, 48000.0);
+        assert!((a_weighted - a_weighted_rms(samples.as_ptr(), 1024, 48000.0)).abs() < 1e-5);
+
+        let distorted: Vec<f32> = samples.iter().map(|s| s.clamp(-0.4, 0.4)).collect();
+        let thd = estimate_thd_with_plan(plan, distorted.as_ptr(), 1024, 48000.0, 1000.0);
+        let expected = estimate_thd(distorted.as_ptr(), 1024, 48000.0, 1000.0);
+        assert!(
+            thd > 0.01 && (thd - expected).abs() < 1e-4,
+            "{thd} vs {expected}"
+        );
+
+        let (mut planned_mel, mut unplanned_mel) = ([0.0f32; 20], [0.0f32; 20]);
+        let out = planned_mel.as_mut_ptr();
+        assert_eq!(
+            compute_log_mel_with_plan(plan, samples.as_ptr(), 1024, 48000.0, 20, out),
+            20
+        );
+        compute_log_mel(
+            samples.as_ptr(),
+            1024,
+            48000.0,
+            20,
+            unplanned_mel.as_mut_ptr(),
+        );
+        // Leakage-floor bands differ in the log by twiddle rounding, so compare
+        // energies against the loudest band.
+        let loudest = unplanned_mel.iter().copied().fold(f32::MIN, f32::max).exp();
+        for (p, u) in planned_mel.iter().zip(&unplanned_mel) {
+            assert!((p.exp() - u.exp()).abs() < 1e-4 * loudest, "{p} vs {u}");
+        }
+
+        let hiss = noise(0.3, 1024, 6);
+        let segment_plan = create_fft_plan(fft::AVERAGING_SEGMENT);
+        let flatness = spectral_flatness_with_plan(segment_plan, hiss.as_ptr(), 1024);
+        assert!((flatness - spectral_flatness(hiss.as_ptr(), 1024)).abs() < 1e-5);
+        destroy_fft_plan(segment_plan);
+
+        // The plan's size wins over the buffer length.
+        let small = create_fft_plan(256);
+        let r = spectral_analyze_with_plan(small, samples.as_ptr(), 1024, 48000.0, false);
+        assert!((unsafe { (*r).dominant_frequency_hz } - 1000.0).abs() <= 48000.0 / 256.0);
+        free_spectrum_result(r);
+        destroy_fft_plan(small);
+        destroy_fft_plan(plan);
+    }
+
+    #[test]
+    fn extract_features_matches_the_standalone_functions() {
+        let samples: Vec<f32> = sine(440.0, 0.4, 44100.0, 2048)
+            .iter()
+            .zip(noise(0.05, 2048, 3))
+            .map(|(s, n)| s + n + 0.02)
+            .collect();
+        let (ptr, len) = (samples.as_ptr(), samples.len());
+        let mut out = [f32::NAN; FEATURE_COUNT + 1];
+
+        assert_eq!(
+            extract_features(ptr, len, 44100.0, out.as_mut_ptr(), out.len()),
+            7
+        );
+        let raw = analyze_audio_chunk(ptr, len);
+        let metrics = unsafe { &*raw };
+        assert_eq!(out[0], metrics.rms);
+        assert_eq!(out[1], peak_amplitude(ptr, len));
+        assert_eq!(out[2], metrics.zcr);
+        assert_eq!(out[3], spectral_centroid(ptr, len, 44100.0));
+        assert_eq!(out[4], spectral_flatness(ptr, len));
+        assert_eq!(out[5], metrics.crest_factor);
+        assert_eq!(out[6], dc_offset(ptr, len));
+        assert!(out[7].is_nan());
+        free_audio_metrics(raw);
+
+        assert_eq!(extract_features(ptr, len, 44100.0, out.as_mut_ptr(), 6), 0);
+        assert_eq!(extract_features(ptr, len, 0.0, out.as_mut_ptr(), 7), 0);
+        assert_eq!(extract_features(ptr, 0, 44100.0, out.as_mut_ptr(), 7), 0);
+    }
+
+    #[test]
+    fn frame_overlap_lays_out_half_overlapping_frames() {
+        let ramp: Vec<f32> = (0..9).map(|i| i as f32).collect();
+        let mut out = [-1.0f32; 13];
+
+        assert_eq!(
+            frame_overlap(ramp.as_ptr(), ramp.len(), 4, 2, out.as_mut_ptr()),
+            3
+        );
+        assert_eq!(
+            out[..12],
+            [0.0, 1.0, 2.0, 3.0, 2.0, 3.0, 4.0, 5.0, 4.0, 5.0, 6.0, 7.0]
+        );
+        // Sample 8 doesn't complete a frame and is left out.
+        assert_eq!(out[12], -1.0);
+
+        assert_eq!(frame_overlap(ramp.as_ptr(), 4, 4, 2, out.as_mut_ptr()), 1);
+        assert_eq!(frame_overlap(ramp.as_ptr(), 3, 4, 2, out.as_mut_ptr()), 0);
+        assert_eq!(frame_overlap(ramp.as_ptr(), 9, 4, 0, out.as_mut_ptr()), 0);
+        assert_eq!(frame_overlap(ramp.as_ptr(), 9, 0, 2, out.as_mut_ptr()), 0);
+        assert_eq!(
+            frame_overlap(ramp.as_ptr(), 9, 4, 2, std::ptr::null_mut()),
+            0
+        );
+    }
+
+    #[test]
+    fn soft_limit_rounds_off_peaks_above_the_threshold() {
+        let original = sine(440.0, 1.8, 48000.0, 2048);
+        let mut samples = original.clone();
+        soft_limit(samples.as_mut_ptr(), samples.len(), 0.6);
+
+        for (out, orig) in samples.iter().zip(&original) {
+            assert!(out.abs() <= 1.0);
+            assert_eq!(out.signum(), orig.signum());
+            if orig.abs() <= 0.6 {
+                assert_eq!(out, orig);
+            } else {
+                assert!(out.abs() > 0.6 && out.abs() <= orig.abs());
+            }
+        }
+
+        // Continuous (and smooth) across the threshold.
+        let mut edge = [0.6f32, 0.6001, -0.6001, 5.0];
+        soft_limit(edge.as_mut_ptr(), edge.len(), 0.6);
+        assert_eq!(edge[0], 0.6);
+        assert!((edge[1] - 0.6001).abs() < 1e-6);
+        assert!((edge[2] + 0.6001).abs() < 1e-6);
+        assert!(edge[3] > 0.99 && edge[3] <= 1.0);
+
+        let mut hard = [1.5f32, -0.9, -2.0];
+        soft_limit(hard.as_mut_ptr(), hard.len(), 1.0);
+        assert_eq!(hard, [1.0, -0.9, -1.0]);
+        soft_limit(std::ptr::null_mut(), 4, 0.5);
+    }
+
+    #[test]
+    fn confidence_is_high_inside_a_category() {
+        let mut confidence = -1.0;
+        let mut classify = |samples: &[f32]| {
+            let code = classify_with_confidence(samples.as_ptr(), samples.len(), &mut confidence);
+            (code, confidence)
+        };
+
+        assert_eq!(classify(&[0.0; 1024]), (0, 1.0));
+        let (code, c) = classify(&[0.001; 1024]);
+        assert!(code == 0 && c > 0.99, "{c}");
+        let full_scale: Vec<f32> = (0..1024)
+            .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        let (code, c) = classify(&full_scale);
+        assert!(code == 2 && c > 0.99, "{c}");
+        let (code, c) = classify(&sine(440.0, 0.15, 44100.0, 1024));
+        assert!(code == 1 && c > 0.99, "{c}");
+    }
+
+    #[test]
+    fn confidence_is_low_on_a_threshold() {
+        let mut confidence = -1.0;
+        let just_audible = [0.0101f32; 1024];
+        assert_eq!(
+            classify_with_confidence(just_audible.as_ptr(), 1024, &mut confidence),
+            1
+        );
+        assert!(confidence < 0.05, "{confidence}");
+
+        let just_clipping = [0.901f32; 1024];
+        assert_eq!(
+            classify_with_confidence(just_clipping.as_ptr(), 1024, &mut confidence),
+            2
+        );
+        assert!(confidence < 0.05, "{confidence}");
+
+        // A Good-level chunk whose flatness sits on the Noisy boundary.
+        let mut metrics = compute_metrics(&sine(440.0, 0.15, 44100.0, 1024));
+        metrics.spectral_flatness = NOISY_FLATNESS;
+        assert_eq!(classification_confidence(&metrics), 0.0);
+
+        assert_eq!(
+            classify_with_confidence(std::ptr::null(), 0, &mut confidence),
+            -1
+        );
+        assert_eq!(confidence, 0.0);
+        assert_eq!(
+            classify_with_confidence(just_audible.as_ptr(), 1024, std::ptr::null_mut()),
+            1
+        );
+    }
+
+    #[test]
+    fn classification_paths_skip_flatness_only_where_it_cannot_matter() {
+        let inputs = [
+            vec![0.0f32; 1024],
+            vec![0.95f32; 1024],
+            sine(440.0, 0.5, 44100.0, 1024),
+            noise(0.3, 1024, 8),
+        ];
+        for samples in &inputs {
+            let full = compute_metrics(samples);
+            let lazy = compute_metrics_with(samples, Flatness::WhenClassifying);
+            assert_eq!(classify_chunk(samples), full.classification);
+            assert_eq!(lazy.rms, full.rms);
+            if full.classification == 1 || full.classification == 3 {
+                assert_eq!(lazy.spectral_flatness, full.spectral_flatness);
+            } else {
+                assert_eq!(lazy.spectral_flatness, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_rms_matches_the_metrics_rms() {
+        let biased: Vec<f32> = sine(441.0, 0.3, 44100.0, 1000)
+            .iter()
+            .map(|s| s + 0.2)
+            .collect();
+        assert!((chunk_rms(&biased) - compute_metrics(&biased).rms).abs() < 1e-6);
+        set_remove_dc(true);
+        let dc_free = chunk_rms(&biased);
+        let expected = compute_metrics(&biased).rms;
+        set_remove_dc(false);
+        assert!((dc_free - expected).abs() < 1e-5, "{dc_free} vs {expected}");
+        assert!((dc_free - 0.3 / 2f32.sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn thresholds_default_and_update() {
+        let mut out = [0.0f32; 2];
+        get_thresholds(out.as_mut_ptr());
+        assert_eq!(out, [0.01, 0.9]);
+
+        // 0.05 RMS is Good by default but Silence under a raised floor.
+        let samples = [0.05f32; 64];
+        assert_eq!(process_audio_chunk(samples.as_ptr(), samples.len()), 1);
+        assert_eq!(set_thresholds(0.1, 0.8), 0);
+        assert_eq!(process_audio_chunk(samples.as_ptr(), samples.len()), 0);
+
+        get_thresholds(out.as_mut_ptr());
+        assert_eq!(out, [0.1, 0.8]);
+    }
+
+    #[test]
+    fn invalid_thresholds_are_rejected() {
+        assert_ne!(set_thresholds(0.5, 0.5), 0);
+        assert_ne!(set_thresholds(0.9, 0.1), 0);
+        assert_ne!(set_thresholds(-0.1, 0.9), 0);
+        assert_ne!(set_thresholds(0.01, 1.5), 0);
+        assert_ne!(set_thresholds(f32::NAN, 0.9), 0);
+
+        let mut out = [0.0f32; 2];
+        get_thresholds(out.as_mut_ptr());
+        assert_eq!(out, [0.01, 0.9]);
+    }
+
+    #[test]
+    fn metrics_layout_matches_documented_offsets() {
+        assert_eq!(std::mem::size_of::<AudioMetrics>(), 28);
+        assert_eq!(std::mem::offset_of!(AudioMetrics, rms), 0);
+        assert_eq!(std::mem::offset_of!(AudioMetrics, peak), 4);
+        assert_eq!(std::mem::offset_of!(AudioMetrics, classification), 8);
+        assert_eq!(std::mem::offset_of!(AudioMetrics, clipped_sample_count), 12);
+        assert_eq!(std::mem::offset_of!(AudioMetrics, zcr), 16);
+        assert_eq!(std::mem::offset_of!(AudioMetrics, crest_factor), 20);
+        assert_eq!(std::mem::offset_of!(AudioMetrics, spectral_flatness), 24);
+    }
+
+    #[test]
+    fn sine_has_low_zcr_and_is_good() {
+        let samples = sine(440.0, 0.5, 44100.0, 1024);
+        let m = compute_metrics(&samples);
+        assert!(m.zcr < 0.05, "zcr = {}", m.zcr);
+        assert_eq!(m.classification, 1);
+    }
+
+    #[test]
+    fn white_noise_has_high_zcr_and_is_noisy() {
+        let samples = noise(0.5, 1024, 7);
+        let m = compute_metrics(&samples);
+        assert!(m.zcr > 0.35, "zcr = {}", m.zcr);
+        assert_eq!(m.classification, 3);
+    }
+
+    #[test]
+    fn dc_biased_signal_has_no_zero_crossings() {
+        let samples: Vec<f32> = sine(440.0, 0.1, 44100.0, 1024)
+            .into_iter()
+            .map(|s| s + 0.5)
+            .collect();
+        let m = compute_metrics(&samples);
+        assert_eq!(m.zcr, 0.0);
+        assert_eq!(m.classification, 1);
     }
 }